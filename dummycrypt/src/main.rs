@@ -16,45 +16,73 @@ along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 Copyright 2014 Charles Hubain <github@haxelion.eu>
 */
-extern crate serialize;
+extern crate subcipher;
 
 use std::vec::Vec;
-use std::iter::repeat;
-use std::cmp::max;
+use std::iter::repeat_n;
 use std::io::prelude::*;
 use std::fs::File;
 use std::env;
-use serialize::hex::FromHex;
+use subcipher::{apply_subs, format_mix, inv_sub, parse_hex, parse_mix};
+use subcipher::pipeline::{Op, Pipeline, Step};
+use subcipher::word::Word;
 
 fn print_usage() {
-    println!("dummycrypt (-e|-d) [-x X] [-a A] [-m M] input output");
-    println!("");
+    println!("dummycrypt (-e|-d) [-i] [-x X] [-a A] [-m M] input output");
+    println!("dummycrypt (-e|-d) [-i] --model ops [-k K]... [(--inc|--idx) D] input output");
+    println!("dummycrypt -s [-i] [-x X] [-a A] [-m M]");
+    println!("dummycrypt -s [-i] --model ops [-k K]... [(--inc|--idx) D]");
+    println!();
     println!("* -e: specify encryption mode");
     println!("* -d: specify decryption mode");
+    println!("* -s: show the key, with the mixes in both notations");
+    println!("* -i: the key components keep their own length instead of being padded, ");
+    println!("each one wrapping around independently.");
     println!("* -x: optional xor hex string of bytes");
     println!("* -a: optional add hex string of bytes");
-    println!("* -m: optional mix hex string of big endian 16 bits unsigned integer");
+    println!("* -m: optional mix hex string of big endian 16 bits unsigned integer or list ");
+    println!("of bit destinations like [1,2,3,4,5,6,7,0][7,6,5,4,3,2,1,0]");
+    println!("* --model: sequence of operations among xor, add, sub, rol, mix, mul and nxor ");
+    println!("separated by commas or dashes, like rol,xor or add-xor-mix, applied in order ");
+    println!("to each byte. affine stands for mul,add and nxor is a xor leaving the bytes ");
+    println!("equal to 00 or to the key unchanged. The default model is xor,add,mix.");
+    println!("The model can also be a word operation like xor32, add32le, add16be or ");
+    println!("sub64le, which xors, adds or subtracts whole little or big endian words with ");
+    println!("carries. Its key is a single -k of hex words, trailing bytes are unchanged.");
+    println!("* -k: key of the next operation of the model, a hex string of bytes or a mix ");
+    println!("string like for -m. Rotations are between 0 and 7 and multipliers are odd. ");
+    println!("Operations without key leave the bytes unchanged.");
+    println!("* --inc: optional hex byte D, the key byte of the first operation at position ");
+    println!("i of the input is incremented by i*D, so the key stream doesn't repeat.");
+    println!("* --idx: optional hex byte D, like --inc but the key byte is xored with i*D.");
     println!("* input: input file name");
     println!("* output: output file name");
-    println!("");
-    println!("The hex strings are padded with zeroes to the same number of elements.");
-    println!("");
+    println!();
+    println!("The keys are padded with zeroes to the same number of elements, unless -i ");
+    println!("is given: with -x 11223344 -a 556677 the key then repeats every 12 bytes.");
+    println!();
     println!("The elements of M represent any of the 40320 possible bijective bit mix ");
-    println!("operations, their encoding is described in the documentation.");
-    println!("");
+    println!("operations, their encoding is described in the documentation. In the list ");
+    println!("notation, the i-th element is the new position of bit i.");
+    println!();
     println!("The cipher encryption algorithm for each byte b is  MIX(ADD(XOR(b,x),a),m)");
     println!("where x, a, m are elements taken from X, A and M respectively and wrap around ");
     println!("when the input is bigger than the key.");
-    println!("");
+    println!();
     println!("Copyright 2015 Charles Hubain <github@haxelion.eu>");
 }
 
-enum Mode {Missing, Encrypt, Decrypt}
+enum Mode {Missing, Encrypt, Decrypt, Show}
 
 fn main() {
-    let mut args : Vec<String> = env::args().collect();
+    let args : Vec<String> = env::args().collect();
     let mut mode : Mode = Mode::Missing;
-    let mut key : Vec<Vec<u8>> = repeat(Vec::<u8>::new()).take(3).collect();
+    let mut key : Vec<Vec<u8>> = repeat_n(Vec::<u8>::new(), 3).collect();
+    let mut model : Option<Pipeline> = None;
+    let mut word : Option<Word> = None;
+    let mut ks : Vec<String> = Vec::new();
+    let mut step : Option<(Step, u8)> = None;
+    let mut independent = false;
     let mut input : &str = "";
     let mut output : &str = "";
     let mut i = 1;
@@ -66,10 +94,16 @@ fn main() {
             "-d" => {
                 mode = Mode::Decrypt;
             },
+            "-s" => {
+                mode = Mode::Show;
+            },
+            "-i" => {
+                independent = true;
+            },
             "-x" => {
                 i += 1;
                 if i < args.len() {
-                    key[0] = match args[i][..].from_hex() {
+                    key[0] = match parse_hex(&args[i][..]) {
                         Ok(h) => h,
                         Err(e) => {
                             println!("xor hex string is invalid: {}", e);
@@ -86,7 +120,7 @@ fn main() {
             "-a" => {
                 i += 1;
                 if i < args.len() {
-                    key[1] = match args[i][..].from_hex() {
+                    key[1] = match parse_hex(&args[i][..]) {
                         Ok(h) => h,
                         Err(e) => {
                             println!("add hex string is invalid: {}", e);
@@ -103,25 +137,74 @@ fn main() {
             "-m" => {
                 i += 1;
                 if i < args.len() {
-                    key[2] = match args[i][..].from_hex() {
+                    key[2] = match parse_mix(&args[i][..]) {
                         Ok(h) => h,
                         Err(e) => {
-                            println!("mix hex string is invalid: {}", e);
+                            println!("mix string is invalid: {}", e);
+                            return;
+                        }
+                    };
+                }
+                else {
+                    println!("You need to provide a mix string after -m");
+                    print_usage();
+                    return;
+                }
+            },
+            "--model" => {
+                i += 1;
+                if i < args.len() {
+                    word = Word::parse(&args[i][..]);
+                    if word.is_none() {
+                        model = match Pipeline::parse(&args[i][..]) {
+                            Ok(p) => Some(p),
+                            Err(e) => {
+                                println!("model is invalid: {}", e);
+                                return;
+                            }
+                        };
+                    }
+                }
+                else {
+                    println!("You need to provide a sequence of operations after --model");
+                    print_usage();
+                    return;
+                }
+            },
+            "--inc" | "--idx" => {
+                let s = Step::from_name(&args[i][2..]).unwrap();
+                i += 1;
+                if i < args.len() {
+                    step = match parse_hex(&args[i][..]) {
+                        Ok(ref h) if h.len() == 1 => Some((s, h[0])),
+                        _ => {
+                            println!("{} step is not a hex byte", s.name());
                             return;
                         }
                     };
                 }
                 else {
-                    println!("You need to provide a mix hex string after -m");
+                    println!("You need to provide a hex byte after --{}", s.name());
+                    print_usage();
+                    return;
+                }
+            },
+            "-k" => {
+                i += 1;
+                if i < args.len() {
+                    ks.push(args[i].clone());
+                }
+                else {
+                    println!("You need to provide a key after -k");
                     print_usage();
                     return;
                 }
             },
             arg => {
-                if input == "" {
+                if input.is_empty() {
                     input = arg;
                 }
-                else if output == "" {
+                else if output.is_empty() {
                     output = arg;
                 }
                 else {
@@ -133,59 +216,133 @@ fn main() {
         }
         i += 1;
     }
-    let length = max(key[0].len(), max(key[1].len(), key[2].len()/2));
-    key[0].resize(length, 0u8);
-    key[1].resize(length, 0u8);
-    key[2].resize(length*2, 0u8);
+    if matches!(mode, Mode::Missing) {
+        print_usage();
+        return;
+    }
+    if let Some(w) = word {
+        if key.iter().any(|k| !k.is_empty()) || step.is_some() || ks.len() != 1 {
+            println!("{} takes a single -k key and no other key option", w.name());
+            return;
+        }
+        let k = match w.parse_key(&ks[0][..]) {
+            Ok(k) => k,
+            Err(e) => {
+                println!("{} key is invalid: {}", w.name(), e);
+                return;
+            }
+        };
+        match mode {
+            Mode::Encrypt => dummy_word_file(input, output, |b| w.encrypt(b, &k)),
+            Mode::Decrypt => dummy_word_file(input, output, |b| w.decrypt(b, &k)),
+            Mode::Show => {
+                for (j, kw) in k.chunks(w.width).enumerate() {
+                    println!("{:2} : {} = {}", j, w.letter(), kw.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+                }
+            },
+            Mode::Missing => print_usage(),
+        };
+        return;
+    }
+    let pipeline = match model {
+        Some(p) => {
+            if key.iter().any(|k| !k.is_empty()) {
+                println!("-x, -a and -m can't be used with --model, use -k instead");
+                return;
+            }
+            if ks.len() > p.ops.len() {
+                println!("{} keys given for {} operations", ks.len(), p.ops.len());
+                return;
+            }
+            key.clear();
+            for (op, k) in p.ops.iter().zip(ks.iter()) {
+                key.push(match op.parse_key(&k[..]) {
+                    Ok(h) => h,
+                    Err(e) => {
+                        println!("{} key is invalid: {}", op.name(), e);
+                        return;
+                    }
+                });
+            }
+            p
+        },
+        None => {
+            if !ks.is_empty() {
+                println!("-k can only be used with --model");
+                return;
+            }
+            Pipeline::parse("xor,add,mix").unwrap()
+        }
+    };
+    if independent {
+        pipeline.fill_key(&mut key);
+    }
+    else {
+        pipeline.pad_key(&mut key);
+    }
+    let subs = match step {
+        Some((s, d)) => {
+            if pipeline.ops[0].key_space() != 256 {
+                println!("{} keys can't be stepped", pipeline.ops[0].name());
+                return;
+            }
+            pipeline.gen_stepped_subs(&key, s, d)
+        },
+        None => pipeline.gen_key_subs(&key)
+    };
     match mode {
-        Mode::Encrypt => dummy_crypt_file(&input, &output, &key),
-        Mode::Decrypt => dummy_decrypt_file(&input, &output, &key),
+        Mode::Encrypt => dummy_crypt_file(input, output, &subs),
+        Mode::Decrypt => dummy_decrypt_file(input, output, &subs),
+        Mode::Show => show_key(&pipeline, &key, step),
         Mode::Missing => print_usage(),
     };
 }
 
-fn gen_sub(x : u8, a : u8, m : u16, sub : &mut [usize; 256]) {
-    let c = [40320u16, 5040, 720, 120, 24, 6, 2, 1, 1];
-    let mut used = [false; 8];
-    let mut p = [0usize; 8];
-    for i in 0usize.. 8 {
-        p[i] = ((m%c[i])/c[i+1]+1) as usize;
-        for j in (0usize..8) {
-            if used[j] == false {
-                p[i] -= 1;
-            }
-            if p[i] == 0 {
-                p[i] = j;
-                used[j] = true;
-                break;
+fn show_key(pipeline : &Pipeline, key : &[Vec<u8>], step : Option<(Step, u8)>) {
+    print!("i ");
+    for op in pipeline.ops.iter() {
+        match *op {
+            Op::Mix => print!(" | m    | m"),
+            _ => print!(" | {} ", op.letter()),
+        }
+    }
+    println!();
+    for i in 0..pipeline.key_length(key) {
+        print!("{:2}", i);
+        for (op, k) in pipeline.ops.iter().zip(pipeline.params_at(key, i)) {
+            match *op {
+                Op::Mix => print!(" : {:04x} : {}", k, format_mix(k)),
+                _ => print!(" : {:02x}", k),
             }
         }
+        println!();
     }
-    for i in 0usize..256 {
-        let b = (i as u8 ^ x) + a;
-        sub[i] = ((b & 1) << p[0] |
-                 ((b & 2) >> 1) << p[1] |
-                 ((b & 4) >> 2) << p[2] |
-                 ((b & 8) >> 3) << p[3] |
-                 ((b & 16) >> 4) << p[4] |
-                 ((b & 32) >> 5) << p[5] |
-                 ((b & 64) >> 6) << p[6] |
-                 ((b & 128) >> 7) << p[7]) as usize;
+    if let Some((s, d)) = step {
+        println!("{} = {:02x}", s.name(), d);
     }
 }
 
-fn inv_sub(sub : &mut [usize; 256]) {
-    let mut c = [0usize; 256];
-    for i in 0usize..256 {
-        c[i] = sub[i];
+fn dummy_crypt_file(input : &str, output : &str, sub : &[[usize; 256]]) {
+    let mut in_file = match File::open(input) {
+        Ok(f) => { f },
+        Err(e) => { println!("Failed to open input file {}: {}!", input, e); return;}
+    };
+    let mut out_file = match File::create(output) {
+        Ok(f) => { f },
+        Err(e) => { println!("Failed to open output file {}: {}!", output, e); return;}
+    };
+    let mut buffer = Vec::<u8>::new();
+    if in_file.read_to_end(&mut buffer).is_err() {
+        println!("Failed to read input file.");
+        return;
     }
-    for i in 0usize..256 {
-        sub[c[i]] = i;
+    apply_subs(&mut buffer, sub);
+    if out_file.write_all(&buffer[..]).is_err() {
+        println!("Failed to write output file.");
     }
 }
 
-
-fn dummy_crypt_file(input : &str, output : &str, key : &Vec<Vec<u8>>) {
+fn dummy_decrypt_file(input : &str, output : &str, sub : &[[usize; 256]]) {
     let mut in_file = match File::open(input) {
         Ok(f) => { f },
         Err(e) => { println!("Failed to open input file {}: {}!", input, e); return;}
@@ -195,24 +352,21 @@ fn dummy_crypt_file(input : &str, output : &str, key : &Vec<Vec<u8>>) {
         Err(e) => { println!("Failed to open output file {}: {}!", output, e); return;}
     };
     let mut buffer = Vec::<u8>::new();
-    let mut sub = Vec::<[usize; 256]>::new();
-    for i in 0..key[0].len() {
-        sub.push([0usize; 256]);
-        gen_sub(key[0][i], key[1][i], ((key[2][2*i] as u16) << 8) + key[2][2*i+1] as u16, &mut sub[i]);
+    let mut sub = sub.to_vec();
+    for s in sub.iter_mut() {
+        inv_sub(s);
     }
     if in_file.read_to_end(&mut buffer).is_err() {
         println!("Failed to read input file.");
         return;
     }
-    for i in 0..buffer.len() {
-        buffer[i] = sub[i%sub.len()][buffer[i] as usize] as u8;
-    }
+    apply_subs(&mut buffer, &sub);
     if out_file.write_all(&buffer[..]).is_err() {
-        println!("Failed to write output file.");
+        println!("Failed to write encrypted file.");
     }
 }
 
-fn dummy_decrypt_file(input : &str, output : &str, key : &Vec<Vec<u8>>) {
+fn dummy_word_file<F : Fn(&mut [u8])>(input : &str, output : &str, f : F) {
     let mut in_file = match File::open(input) {
         Ok(f) => { f },
         Err(e) => { println!("Failed to open input file {}: {}!", input, e); return;}
@@ -222,20 +376,12 @@ fn dummy_decrypt_file(input : &str, output : &str, key : &Vec<Vec<u8>>) {
         Err(e) => { println!("Failed to open output file {}: {}!", output, e); return;}
     };
     let mut buffer = Vec::<u8>::new();
-    let mut sub = Vec::<[usize; 256]>::new();
-    for i in 0..key[0].len() {
-        sub.push([0usize; 256]);
-        gen_sub(key[0][i], key[1][i], ((key[2][2*i] as u16) << 8) + key[2][2*i+1] as u16, &mut sub[i]);
-        inv_sub(&mut sub[i]);
-    }
     if in_file.read_to_end(&mut buffer).is_err() {
         println!("Failed to read input file.");
         return;
     }
-    for i in 0..buffer.len() {
-        buffer[i] = sub[i%sub.len()][buffer[i] as usize] as u8;
-    }
+    f(&mut buffer);
     if out_file.write_all(&buffer[..]).is_err() {
-        println!("Failed to write encrypted file.");
+        println!("Failed to write output file.");
     }
 }
@@ -0,0 +1,225 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::{max, min};
+
+use super::{Columns, Probabilistic, Sample, find_length_candidates};
+use crate::lcg::Lcg;
+use crate::model::{CipherModel, XorModel};
+use crate::scorer::Scorer;
+
+pub const DEFAULT_WINDOW : usize = 4096;
+
+// Windows below this entropy in bits per byte are padding or constant data.
+const LOW_ENTROPY : f64 = 1.0;
+
+// Windows whose entropy is within this margin of the expected entropy of
+// random bytes are compressed or strongly encrypted.
+const RANDOM_MARGIN : f64 = 0.3;
+
+// Factor by which the best key length has to beat length 1 for a window to be
+// periodic. Aligned structures of plain binary data reach about 1.1.
+const PERIODIC_MARGIN : f64 = 1.25;
+
+// Inputs whose columns are this close to uniform for every key length are
+// unlikely to be enciphered with a byte substitution, which keeps the
+// structure of the plaintext. Deflate streams reach about 1.6.
+const UNIFORM_THRESHOLD : f64 = 2.0;
+
+// Fraction of the gap between the score of random bytes and a perfect score a
+// key has to reach to be reliable.
+const RELIABILITY_MARGIN : f64 = 0.25;
+
+// Largest number of random bytes the reliability threshold is calibrated on.
+// Random bytes score a little lower on larger inputs, so the threshold of those
+// is slightly conservative.
+const CALIBRATION_BYTES : usize = 65536;
+
+// Proportion of printable, whitespace and non-ASCII bytes of text.
+const TEXT_RATIO : f64 = 0.95;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Class {
+    Constant,
+    Random,
+    // Periodic data, like a substitution with a repeating key of that length.
+    Substitution(usize),
+    Plaintext,
+    // Not periodic but not recognised as plaintext, either plaintext unlike
+    // the sample or a single byte key.
+    Unknown
+}
+
+impl Class {
+    pub fn describe(&self) -> String {
+        match *self {
+            Class::Constant => "constant or padding".to_string(),
+            Class::Random => "compressed or strongly encrypted".to_string(),
+            Class::Substitution(l) => format!("substitution, key length {}", l),
+            Class::Plaintext => "plaintext".to_string(),
+            Class::Unknown => "plaintext or single byte key".to_string()
+        }
+    }
+}
+
+pub struct Window {
+    pub offset : usize,
+    pub length : usize,
+    pub entropy : f64,
+    // Best key length and how much it beats length 1.
+    pub period : usize,
+    pub periodicity : f64,
+    pub class : Class
+}
+
+pub struct Region {
+    pub start : usize,
+    pub end : usize,
+    pub entropy : f64,
+    pub class : Class
+}
+
+// Shannon entropy in bits per byte.
+pub fn entropy(data : &[u8]) -> f64 {
+    let mut freq = [0u64; 256];
+    for b in data.iter() {
+        freq[*b as usize] += 1;
+    }
+    let mut h = 0f64;
+    for f in freq.iter() {
+        if *f > 0 {
+            let p = *f as f64 / data.len() as f64;
+            h -= p * p.log2();
+        }
+    }
+    h
+}
+
+// Expected entropy of n random bytes, lower than 8 bits as some bytes are
+// missing from small windows.
+fn random_entropy(n : usize) -> f64 {
+    8f64 - 255f64 / (2f64 * n as f64 * 2f64.ln())
+}
+
+// Whether the data looks like text, bytes above 0x7f being allowed for UTF-8
+// and other extended encodings. Empty data isn't text.
+pub(crate) fn is_text(data : &[u8]) -> bool {
+    let text = data.iter().filter(|b| (**b >= 0x20 && **b < 0x7f) || **b == b'\n' || **b == b'\r' ||
+                                      **b == b'\t' || **b >= 0x80).count();
+    !data.is_empty() && text as f64 >= TEXT_RATIO * data.len() as f64
+}
+
+// Classify a window of the input. The periodicity is the one of the key
+// length detection. Data which isn't periodic is plaintext if the sample
+// matches it best without any xor key, or without sample if it is text.
+pub fn analyze_window(data : &[u8], offset : usize, max_l : usize, sample : Option<&Sample>, scorer : &dyn Scorer)
+                      -> Window {
+    let h = entropy(data);
+    let mut length : Vec<Probabilistic<usize>> = Vec::new();
+    // Length 1 is always a candidate, whatever the maximum length.
+    find_length_candidates(&[data], &mut length, max(max_l, 1), 0);
+    let p1 = length.iter().find(|l| l.v == 1).map_or(0f64, |l| l.p);
+    let periodicity = if p1 > 0f64 { length[0].p / p1 } else { 1f64 };
+    let class = if h < LOW_ENTROPY {
+        Class::Constant
+    }
+    else if h > random_entropy(data.len()) - RANDOM_MARGIN {
+        Class::Random
+    }
+    else if length[0].v > 1 && periodicity > PERIODIC_MARGIN {
+        Class::Substitution(length[0].v)
+    }
+    else {
+        match sample {
+            Some(s) => {
+                let mut key : Vec<Vec<u8>> = Vec::new();
+                XorModel.break_key(&Columns::new(&[data], 1, 0), s, scorer, &mut key);
+                if key[0][0] == 0 { Class::Plaintext } else { Class::Substitution(1) }
+            },
+            None => if is_text(data) { Class::Plaintext } else { Class::Unknown }
+        }
+    };
+    Window {offset, length : data.len(), entropy : h, period : length[0].v, periodicity,
+            class}
+}
+
+// Split the input in windows of about the given size and classify each one.
+pub fn entropy_map(data : &[u8], window : usize, max_l : usize, sample : Option<&Sample>, scorer : &dyn Scorer)
+                   -> Vec<Window> {
+    let n = max(1, (data.len() + window / 2) / window);
+    (0..n).map(|j| {
+        let (start, end) = (j * data.len() / n, (j + 1) * data.len() / n);
+        analyze_window(&data[start..end], start, max_l, sample, scorer)
+    }).collect()
+}
+
+// Merge the consecutive windows of the same class.
+pub fn regions(windows : &[Window]) -> Vec<Region> {
+    let mut regions : Vec<Region> = Vec::new();
+    for w in windows.iter() {
+        if let Some(r) = regions.last_mut() {
+            if r.class == w.class {
+                let l = (r.end - r.start) as f64;
+                r.entropy = (r.entropy * l + w.entropy * w.length as f64) / (l + w.length as f64);
+                r.end = w.offset + w.length;
+                continue;
+            }
+        }
+        regions.push(Region {start : w.offset, end : w.offset + w.length, entropy : w.entropy, class : w.class});
+    }
+    regions
+}
+
+// Chi-squared statistic of the columns for key length l against the uniform
+// distribution, divided by its degrees of freedom: about 1 for random bytes.
+pub fn uniformity(data : &[u8], l : usize) -> f64 {
+    let mut total = 0f64;
+    for p in 0..l {
+        let mut freq = [0u64; 256];
+        let mut n = 0u64;
+        let mut i = p;
+        while i < data.len() {
+            freq[data[i] as usize] += 1;
+            n += 1;
+            i += l;
+        }
+        let e = n as f64 / 256f64;
+        total += freq.iter().fold(0f64, |a, &f| a + (f as f64 - e) * (f as f64 - e) / e) / 255f64;
+    }
+    total / l as f64
+}
+
+// Whether the input is near-uniform without any periodic structure, like
+// compressed or strongly encrypted data. Only the key lengths leaving at least
+// 256 bytes per column are tested, the statistic being unreliable below.
+pub fn unlikely_substitution(data : &[u8], max_l : usize) -> bool {
+    data.len() >= 256 && (1..max_l+1).filter(|&l| data.len() / l >= 256)
+                                        .all(|l| uniformity(data, l) < UNIFORM_THRESHOLD)
+}
+
+// Score below which a key of the model is unreliable: the score the model
+// reaches on random bytes of the same size (capped) and key length, plus a
+// margin.
+pub fn reliability_threshold(model : &dyn CipherModel, size : usize, length : usize, block : usize, sample : &Sample,
+                             scorer : &dyn Scorer) -> f64 {
+    let random = Lcg::from_name("msvc").unwrap().keystream(1, min(size, CALIBRATION_BYTES));
+    let mut key : Vec<Vec<u8>> = Vec::new();
+    let s = model.break_key(&Columns::new(&[&random[..]], length, block), sample, scorer, &mut key);
+    s + RELIABILITY_MARGIN * (1f64 - s)
+}
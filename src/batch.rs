@@ -0,0 +1,157 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::{max, min};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use super::{Candidate, Sample, crack, decipher, walk_files};
+use crate::filetype;
+use crate::model::CipherModel;
+use crate::scorer::Scorer;
+
+pub struct FileResult {
+    pub path : PathBuf,
+    pub size : usize,
+    // Best key, None if the file couldn't be broken.
+    pub candidate : Option<Candidate>,
+    // Type of the deciphered file.
+    pub file_type : Option<String>,
+    // Error reading the file or writing its output.
+    pub error : Option<String>
+}
+
+// Whether a file name matches a pattern where * is any sequence of bytes and ?
+// any byte.
+pub fn matches_wildcard(pattern : &[u8], name : &[u8]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(&b'*') => (0..name.len()+1).any(|i| matches_wildcard(&pattern[1..], &name[i..])),
+        Some(&b'?') => !name.is_empty() && matches_wildcard(&pattern[1..], &name[1..]),
+        Some(c) => name.first() == Some(c) && matches_wildcard(&pattern[1..], &name[1..])
+    }
+}
+
+fn sorted(mut paths : Vec<PathBuf>) -> Vec<PathBuf> {
+    paths.sort();
+    paths
+}
+
+// Files of the inputs: files, directories whose files are all used and
+// patterns with wildcards in their file name like dumps/*.bin, in the order of
+// the inputs and sorted by name inside directories and patterns.
+pub fn expand_inputs(inputs : &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files : Vec<PathBuf> = Vec::new();
+    for input in inputs.iter() {
+        let p = Path::new(input);
+        let found = if p.is_dir() {
+            match walk_files(p) {
+                Ok(f) => sorted(f),
+                Err(e) => return Err(format!("Could not read directory {}: {}", input, e))
+            }
+        }
+        else if input.contains('*') || input.contains('?') {
+            let pattern = p.file_name().map_or(Vec::new(), |n| n.as_encoded_bytes().to_vec());
+            let dir = match p.parent() {
+                Some(d) if !d.as_os_str().is_empty() => d,
+                _ => Path::new(".")
+            };
+            match fs::read_dir(dir) {
+                Ok(f) => sorted(f.filter_map(|e| e.ok().map(|e| e.path())).filter(|f| {
+                    f.is_file() && f.file_name().is_some_and(|n| matches_wildcard(&pattern, n.as_encoded_bytes()))
+                }).collect()),
+                Err(e) => return Err(format!("Could not read directory of {}: {}", input, e))
+            }
+        }
+        else if p.is_file() {
+            vec![p.to_path_buf()]
+        }
+        else {
+            return Err(format!("{} is not a file or a directory", input));
+        };
+        for f in found.into_iter() {
+            if !files.contains(&f) {
+                files.push(f);
+            }
+        }
+    }
+    Ok(files)
+}
+
+// Inputs listed in a file, one per line.
+pub fn read_file_list(path : &str) -> Result<Vec<String>, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(s.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect()),
+        Err(e) => Err(format!("Could not read file list {}: {}", path, e))
+    }
+}
+
+fn crack_file(models : &[Box<dyn CipherModel>], path : &Path, sample : &Sample, scorer : &dyn Scorer, max_length : usize,
+              block : usize, output : Option<&Path>) -> FileResult {
+    let mut result = FileResult {path : path.to_path_buf(), size : 0, candidate : None, file_type : None,
+                                error : None};
+    let data = match fs::read(path) {
+        Ok(d) => d,
+        Err(e) => {
+            result.error = Some(format!("Could not read input file: {}", e));
+            return result;
+        }
+    };
+    result.size = data.len();
+    result.candidate = crack(models, &[&data[..]], sample, scorer, max_length, block);
+    // Only the header is needed for the type when the file isn't written.
+    let plain = match result.candidate {
+        Some(ref c) if output.is_some() => Some(decipher(&*c.model, &c.key, &data[..], block)),
+        Some(ref c) => Some(decipher(&*c.model, &c.key, &data[..min(data.len(), filetype::HEADER_BYTES)], block)),
+        None => None
+    };
+    result.file_type = plain.as_ref().map(|p| filetype::describe(&p[..]));
+    if let (Some(plain), Some(out)) = (plain, output) {
+        if let Err(e) = fs::write(out, &plain) {
+            result.error = Some(format!("Could not write output file: {}", e));
+        }
+    }
+    result
+}
+
+// Break the files with jobs threads, sharing the sample, and write the
+// deciphered files to their outputs if given. Results are in file order.
+#[allow(clippy::too_many_arguments)]
+pub fn crack_files(models : &[Box<dyn CipherModel>], files : &[PathBuf], sample : &Sample, scorer : &dyn Scorer,
+                   max_length : usize, block : usize, jobs : usize, outputs : Option<&[PathBuf]>)
+                   -> Vec<FileResult> {
+    let jobs = max(1, min(jobs, files.len()));
+    let mut threads : Vec<_> = thread::scope(|s| {
+        let handles : Vec<_> = (0..jobs).map(|t| {
+            s.spawn(move || {
+                let mut results : Vec<FileResult> = Vec::new();
+                let mut j = t;
+                while j < files.len() {
+                    results.push(crack_file(models, &files[j], sample, scorer, max_length, block,
+                                            outputs.map(|o| o[j].as_path())));
+                    j += jobs;
+                }
+                results
+            })
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap().into_iter()).collect()
+    });
+    (0..files.len()).map(|j| threads[j % jobs].next().unwrap()).collect()
+}
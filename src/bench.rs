@@ -0,0 +1,116 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use crate::rng::Rng;
+
+use super::{Candidate, CipherModel, Metric, Sample, crack, normalize_key, same_subs};
+use crate::model::{XorAddModel, XorModel};
+
+pub struct BenchConfig {
+    pub models : Vec<Box<dyn CipherModel>>,
+    pub lengths : Vec<usize>,
+    pub sizes : Vec<usize>,
+    pub trials : usize,
+    pub max_length : usize,
+    pub metric : Metric,
+    pub seed : usize
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BenchConfig {
+    pub fn new() -> BenchConfig {
+        BenchConfig {
+            models : vec![Box::new(XorModel) as Box<dyn CipherModel>, Box::new(XorAddModel) as Box<dyn CipherModel>],
+            lengths : vec![1, 2, 3, 4, 8],
+            sizes : vec![1024, 4096, 16384],
+            trials : 5,
+            max_length : 10,
+            metric : Metric::L2,
+            seed : 1337
+        }
+    }
+}
+
+pub struct BenchResult {
+    pub model : Box<dyn CipherModel>,
+    pub length : usize,
+    pub size : usize,
+    pub trials : usize,
+    pub recovered : usize
+}
+
+// Encrypt slices of the corpus with random keys for every model, key length and
+// ciphertext size of the configuration, crack them and count how many keys were
+// recovered. The corpus must not be empty.
+pub fn run_bench(corpus : &[u8], sample : &Sample, config : &BenchConfig) -> Vec<BenchResult> {
+    let mut rng = Rng::new(config.seed as u64);
+    let mut results : Vec<BenchResult> = Vec::new();
+    for model in config.models.iter() {
+        for &l in config.lengths.iter() {
+            for &size in config.sizes.iter() {
+                let mut recovered = 0usize;
+                for _ in 0..config.trials {
+                    let key = model.random_key(&mut rng, l);
+                    let offset = rng.gen_range(0, corpus.len());
+                    let plain : Vec<u8> = (0..size).map(|i| corpus[(offset + i) % corpus.len()]).collect();
+                    let data = encrypt(&plain[..], &**model, &key);
+                    match crack(std::slice::from_ref(model), &[&data[..]], sample, &config.metric, config.max_length, 0) {
+                        Some(ref c) if key_recovered(&**model, &key, c) => recovered += 1,
+                        _ => {}
+                    }
+                }
+                results.push(BenchResult {
+                    model : model.clone(),
+                    length : l,
+                    size,
+                    trials : config.trials,
+                    recovered
+                });
+            }
+        }
+    }
+    results
+}
+
+// Fraction of the keys of a model which were recovered.
+pub fn recovery_rate(results : &[BenchResult], model : &dyn CipherModel) -> f64 {
+    let mut trials = 0usize;
+    let mut recovered = 0usize;
+    for r in results.iter().filter(|r| r.model.name() == model.name()) {
+        trials += r.trials;
+        recovered += r.recovered;
+    }
+    recovered as f64 / trials as f64
+}
+
+// Encrypt with the model under test, whose encipher is the inverse of the
+// decipher the breaker scores keys with.
+fn encrypt(plain : &[u8], model : &dyn CipherModel, key : &[Vec<u8>]) -> Vec<u8> {
+    model.encipher(key, plain)
+}
+
+fn key_recovered(model : &dyn CipherModel, key : &[Vec<u8>], found : &Candidate) -> bool {
+    let (nm, nkey) = normalize_key(model, key);
+    same_subs(&*nm, &nkey, &*found.model, &found.key)
+}
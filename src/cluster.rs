@@ -0,0 +1,112 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::Ordering;
+
+use super::Candidate;
+use crate::model::CipherModel;
+
+// A file of a cluster, its key being the key of the first file of the cluster
+// started at position phase.
+pub struct Member {
+    pub file : usize,
+    pub phase : usize,
+    pub score : f64
+}
+
+// Files whose keys are the same up to a rotation, as when they are fragments
+// of a stream enciphered with one key or were enciphered starting at different
+// key positions. Keys are compared through their normalised substitution
+// tables, so keys differing by their period or by equivalent components are
+// the same.
+pub struct Cluster {
+    pub model : Box<dyn CipherModel>,
+    pub key : Vec<Vec<u8>>,
+    pub members : Vec<Member>,
+    subs : Vec<[usize; 256]>
+}
+
+impl Cluster {
+    pub fn mean_score(&self) -> f64 {
+        self.members.iter().fold(0f64, |a, m| a + m.score) / self.members.len() as f64
+    }
+}
+
+fn rotation_cmp(subs : &[[usize; 256]], r1 : usize, r2 : usize) -> Ordering {
+    let l = subs.len();
+    for p in 0..l {
+        match subs[(p + r1) % l][..].cmp(&subs[(p + r2) % l][..]) {
+            Ordering::Equal => {},
+            o => return o
+        }
+    }
+    Ordering::Equal
+}
+
+// Rotation giving the smallest substitution tables. Only the keys of
+// substitution models can start anywhere: the carries of the word models
+// depend on the position in the word.
+fn canonical_rotation(model : &dyn CipherModel, subs : &[[usize; 256]]) -> usize {
+    let mut best = 0usize;
+    if model.is_substitution() {
+        for r in 1..subs.len() {
+            if rotation_cmp(subs, r, best) == Ordering::Less {
+                best = r;
+            }
+        }
+    }
+    best
+}
+
+fn rotated(subs : &[[usize; 256]], r : usize) -> Vec<[usize; 256]> {
+    (0..subs.len()).map(|p| subs[(p + r) % subs.len()]).collect()
+}
+
+// Group the files by key family, the candidates being the normalised best key
+// of each file, None for the files which couldn't be broken. Clusters are
+// sorted by decreasing number of files.
+pub fn cluster_keys(candidates : &[Option<Candidate>]) -> Vec<Cluster> {
+    let mut clusters : Vec<(Cluster, usize)> = Vec::new();
+    for (file, candidate) in candidates.iter().enumerate() {
+        let c = match *candidate {
+            Some(ref c) => c,
+            None => continue
+        };
+        let subs = c.model.gen_key_subs(&c.key);
+        let r = canonical_rotation(&*c.model, &subs[..]);
+        let canonical = rotated(&subs[..], r);
+        let l = subs.len();
+        let family = clusters.iter().position(|(k, _)| k.model.name() == c.model.name() &&
+            k.subs.len() == l && (0..l).all(|p| k.subs[p][..] == canonical[p][..]));
+        match family {
+            Some(i) => {
+                let r0 = clusters[i].1;
+                clusters[i].0.members.push(Member {file, phase : (r0 + l - r) % l, score : c.score});
+            },
+            None => {
+                let k = Cluster {model : c.model.clone(), key : c.key.clone(), subs : canonical,
+                                 members : vec![Member {file, phase : 0, score : c.score}]};
+                clusters.push((k, r));
+            }
+        }
+    }
+    let mut clusters : Vec<Cluster> = clusters.into_iter().map(|(k, _)| k).collect();
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.members.len()));
+    clusters
+}
@@ -0,0 +1,190 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use subcipher::{lcm, parse_hex};
+use subcipher::pipeline::{Op, Pipeline};
+
+// Values allowed for the key of each operation of a pipeline at each key
+// position, None allowing any value. The positions of each operation wrap
+// around like independent key components, and an operation without positions
+// is not constrained.
+#[derive(Clone)]
+pub struct Constraints {
+    pub ops : Vec<Vec<Option<Vec<u16>>>>
+}
+
+// Every key of one position of an operation.
+fn all_params(op : Op) -> Vec<u16> {
+    (0..op.key_space()).map(|i| op.param(i)).collect()
+}
+
+// Intersection of two sets of allowed values, None being any value.
+fn intersect(a : &Option<Vec<u16>>, b : &Option<Vec<u16>>) -> Option<Vec<u16>> {
+    match (a, b) {
+        (&None, _) => b.clone(),
+        (_, &None) => a.clone(),
+        (Some(a), Some(b)) => Some(a.iter().filter(|v| b.contains(v)).copied().collect())
+    }
+}
+
+// Value of one position of a fixed key, ?? (or ???? and [?] for mixes) being
+// unknown.
+fn parse_fixed(op : Op, s : &str) -> Result<Option<u16>, String> {
+    if s.chars().all(|c| c == '?') || s == "[?]" {
+        return Ok(None);
+    }
+    let k = op.parse_key(s)?;
+    Ok(Some(match op {
+        Op::Mix => ((k[0] as u16) << 8) + k[1] as u16,
+        _ => k[0] as u16
+    }))
+}
+
+// Values of a range like 20-7e, printable standing for 20-7e and ?? for any
+// value.
+fn parse_range(op : Op, s : &str) -> Result<Option<Vec<u16>>, String> {
+    if s == "??" || s == "any" {
+        return Ok(None);
+    }
+    let r = if s == "printable" { "20-7e" } else { s };
+    let bounds : Vec<&str> = r.split('-').collect();
+    if bounds.len() != 2 {
+        return Err(format!("{} is not a range like 20-7e", s));
+    }
+    let parse = |b : &str| -> Result<u16, String> {
+        let h = parse_hex(b)?;
+        match h.len() {
+            1 => Ok(h[0] as u16),
+            2 => Ok(((h[0] as u16) << 8) + h[1] as u16),
+            _ => Err(format!("{} is not a range bound", b))
+        }
+    };
+    let (lo, hi) = (parse(bounds[0])?, parse(bounds[1])?);
+    let values : Vec<u16> = all_params(op).into_iter().filter(|v| *v >= lo && *v <= hi).collect();
+    if values.is_empty() {
+        return Err(format!("no {} key is in {}", op.name(), s));
+    }
+    Ok(Some(values))
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Constraints {
+    pub fn new() -> Constraints {
+        Constraints {ops : Vec::new()}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.iter().all(|c| c.is_empty())
+    }
+
+    // Add a constraint written c=values, c being the key letter of an
+    // operation followed by its occurrence number when the pipeline has
+    // several operations with that letter, like x2. A fixed key is a hex
+    // string with ?? for the unknown bytes (mixes also accept the list
+    // notation with [?]), a range is a comma separated list of per position
+    // ranges like 20-7e or printable.
+    pub fn add(&mut self, pipeline : &Pipeline, spec : &str, range : bool) -> Result<(), String> {
+        let i = match spec.find('=') {
+            Some(i) => i,
+            None => return Err(format!("{} is not like x=value", spec))
+        };
+        let name = &spec[..i];
+        // The letter can be any character, split it without slicing by byte.
+        let mut chars = name.chars();
+        let letter = match chars.next() {
+            Some(l) => l,
+            None => return Err(format!("{} has no key letter", spec))
+        };
+        let number = chars.as_str();
+        let occurrence : usize = if number.is_empty() {
+            1
+        } else {
+            match number.parse().ok() {
+                Some(n) if n > 0 => n,
+                _ => return Err(format!("{} is not a key component", name))
+            }
+        };
+        let j = match pipeline.ops.iter().enumerate().filter(|&(_, op)| op.letter() == letter).nth(occurrence - 1) {
+            Some((j, _)) => j,
+            None => return Err(format!("{} has no key {}", pipeline.name(), name))
+        };
+        let op = pipeline.ops[j];
+        let value = &spec[i+1..];
+        let mut positions : Vec<Option<Vec<u16>>> = Vec::new();
+        if range {
+            for r in value.split(',') {
+                positions.push(parse_range(op, r.trim())?);
+            }
+        }
+        else if op == Op::Mix && value.starts_with('[') {
+            for list in value.split(']').filter(|l| !l.is_empty()) {
+                positions.push(parse_fixed(op, &format!("{}]", list)[..])?.map(|v| vec![v]));
+            }
+        }
+        else {
+            let digits = 2 * op.key_size();
+            let chars : Vec<char> = value.chars().collect();
+            if chars.is_empty() || !chars.len().is_multiple_of(digits) {
+                return Err(format!("{} is not made of {} hex digits keys", value, digits));
+            }
+            for c in chars.chunks(digits) {
+                let s : String = c.iter().copied().collect();
+                positions.push(parse_fixed(op, &s[..])?.map(|v| vec![v]));
+            }
+        }
+        while self.ops.len() < pipeline.ops.len() {
+            self.ops.push(Vec::new());
+        }
+        let old = self.ops[j].clone();
+        if old.is_empty() {
+            self.ops[j] = positions;
+        }
+        else {
+            let l = lcm(old.len(), positions.len());
+            self.ops[j] = (0..l).map(|p| intersect(&old[p % old.len()], &positions[p % positions.len()])).collect();
+        }
+        if self.ops[j].iter().any(|v| v.as_ref().is_some_and(|v| v.is_empty())) {
+            return Err(format!("the constraints of {} leave no key", name));
+        }
+        Ok(())
+    }
+
+    // Values allowed for each operation at key position p.
+    pub fn allowed(&self, pipeline : &Pipeline, p : usize) -> Vec<Vec<u16>> {
+        pipeline.ops.iter().enumerate().map(|(j, op)| {
+            match self.ops.get(j) {
+                Some(c) if !c.is_empty() => match c[p % c.len()] {
+                    Some(ref v) => v.clone(),
+                    None => all_params(*op)
+                },
+                _ => all_params(*op)
+            }
+        }).collect()
+    }
+
+    // Number of constrained positions after which the constraints repeat.
+    pub fn period(&self) -> usize {
+        self.ops.iter().filter(|c| !c.is_empty()).fold(1, |l, c| lcm(l, c.len()))
+    }
+}
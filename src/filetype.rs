@@ -0,0 +1,166 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::min;
+
+use super::{Candidate, decipher};
+use crate::analyze::is_text;
+
+// Bytes of the start of the plaintext examined to detect its type.
+pub const HEADER_BYTES : usize = 4096;
+
+// Score bonus of the candidates whose plaintext starts with a valid header.
+pub const MAGIC_BONUS : f64 = 0.02;
+
+pub struct FileType {
+    pub name : String,
+    // Whether the type was recognised from a signature or a structure, rather
+    // than from the bytes being text.
+    pub magic : bool
+}
+
+impl FileType {
+    fn new(name : &str, magic : bool) -> Option<FileType> {
+        Some(FileType {name : name.to_string(), magic})
+    }
+}
+
+fn read_u32_le(data : &[u8], offset : usize) -> Option<usize> {
+    if offset + 4 > data.len() {
+        return None;
+    }
+    Some((data[offset] as usize) | (data[offset+1] as usize) << 8 | (data[offset+2] as usize) << 16 |
+         (data[offset+3] as usize) << 24)
+}
+
+// An MZ header whose e_lfanew points to the PE signature is a PE executable,
+// its machine telling 32 from 64 bits.
+fn detect_mz(data : &[u8]) -> Option<FileType> {
+    match read_u32_le(data, 0x3c) {
+        Some(pe) if pe + 6 <= data.len() && &data[pe..pe+4] == b"PE\0\0" => {
+            match (data[pe+4], data[pe+5]) {
+                (0x64, 0x86) => FileType::new("PE32+ executable (x86-64)", true),
+                (0x4c, 0x01) => FileType::new("PE32 executable (i386)", true),
+                _ => FileType::new("PE executable", true)
+            }
+        },
+        _ => FileType::new("MS-DOS executable", true)
+    }
+}
+
+fn detect_elf(data : &[u8]) -> Option<FileType> {
+    match data.get(4) {
+        Some(&1) => FileType::new("ELF 32-bit", true),
+        Some(&2) => FileType::new("ELF 64-bit", true),
+        _ => FileType::new("ELF", true)
+    }
+}
+
+// Interpreter of a #! line, like sh for #!/bin/sh or python for
+// #!/usr/bin/env python.
+fn detect_script(data : &[u8]) -> Option<FileType> {
+    let line = data[2..].split(|b| *b == b'\n').next().unwrap();
+    let words : Vec<&[u8]> = line.split(|b| *b == b' ' || *b == b'\t' || *b == b'\r')
+                                 .filter(|w| !w.is_empty()).collect();
+    let program = match words.first() {
+        Some(p) => p.split(|b| *b == b'/').next_back().unwrap_or(*p),
+        None => return None
+    };
+    let program = if program == b"env" && words.len() > 1 { words[1] } else { program };
+    match String::from_utf8(program.to_vec()) {
+        Ok(p) => FileType::new(&format!("{} script", p)[..], true),
+        Err(_) => None
+    }
+}
+
+// HTML starts, once the blanks are skipped, with a doctype or an html or head
+// tag.
+fn detect_html(data : &[u8]) -> Option<FileType> {
+    let start = data.iter().position(|b| !(*b == b' ' || *b == b'\t' || *b == b'\r' || *b == b'\n'))
+                    .unwrap_or(data.len());
+    let head = data[start..min(data.len(), start + 16)].to_ascii_lowercase();
+    for tag in [&b"<!doctype html"[..], &b"<html"[..], &b"<head"[..]].iter() {
+        if head.starts_with(tag) {
+            return FileType::new("HTML document", true);
+        }
+    }
+    None
+}
+
+// Type of the data from its first bytes, None for unrecognised binary data.
+pub fn detect(data : &[u8]) -> Option<FileType> {
+    let data = &data[..min(data.len(), HEADER_BYTES)];
+    // A UTF-8 byte order mark can start text formats.
+    let text = if data.starts_with(b"\xef\xbb\xbf") { &data[3..] } else { data };
+    if data.starts_with(b"MZ") && data.len() >= 0x40 {
+        detect_mz(data)
+    }
+    else if data.starts_with(b"\x7fELF") {
+        detect_elf(data)
+    }
+    else if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+        FileType::new("Zip archive", true)
+    }
+    else if data.starts_with(b"%PDF-") {
+        FileType::new("PDF document", true)
+    }
+    else if data.starts_with(b"\x1f\x8b\x08") {
+        FileType::new("gzip compressed data", true)
+    }
+    else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        FileType::new("PNG image", true)
+    }
+    else if text.starts_with(b"#!") && is_text(text) {
+        detect_script(text)
+    }
+    else if let Some(t) = detect_html(text) {
+        Some(t)
+    }
+    else if is_text(text) {
+        FileType::new("text", false)
+    }
+    else {
+        None
+    }
+}
+
+// Name of the type of the data, data when it isn't recognised like file does.
+pub fn describe(data : &[u8]) -> String {
+    match detect(data) {
+        Some(t) => t.name,
+        None => "data".to_string()
+    }
+}
+
+// Whether the input deciphered with the key of the candidate starts with a
+// valid header.
+pub fn has_magic(candidate : &Candidate, data : &[u8], block : usize) -> bool {
+    let header = &data[..min(data.len(), HEADER_BYTES)];
+    detect(&decipher(&*candidate.model, &candidate.key, header, block)[..]).is_some_and(|t| t.magic)
+}
+
+// Raise the score of the candidates whose deciphered input starts with a valid
+// header, a strong hint that the key is right.
+pub fn boost_candidates(candidates : &mut [Candidate], data : &[u8], block : usize) {
+    for c in candidates.iter_mut() {
+        if c.score != 0f64 && has_magic(c, data, block) {
+            c.score += MAGIC_BONUS;
+        }
+    }
+}
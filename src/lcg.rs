@@ -0,0 +1,318 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::thread;
+
+use subcipher::parse_hex;
+use super::{Sample, identity_sub, num_cpus};
+use crate::scorer::Scorer;
+
+// Linear congruential generator s' = (a * s + c) mod m, each step giving the
+// keystream byte s' >> shift. The seed is the state before the first step, like
+// srand() followed by rand() calls.
+#[derive(Clone)]
+pub struct Lcg {
+    pub name : String,
+    pub a : u64,
+    pub c : u64,
+    pub m : u64,
+    pub shift : usize
+}
+
+// Seeds tried by default when scoring the keystream statistically.
+pub const DEFAULT_STAT_SEEDS : u64 = 1 << 24;
+
+// Number of bytes deciphered to score a seed statistically.
+const STAT_BYTES : usize = 256;
+
+// Largest shift find_byte_lcgs supports, its cost growing like 2^(3 * shift).
+pub const MAX_BYTE_SHIFT : usize = 10;
+
+impl Lcg {
+    pub fn new(name : &str, a : u64, c : u64, m : u64, shift : usize) -> Lcg {
+        Lcg {name : name.to_string(), a, c, m, shift}
+    }
+
+    // Well-known generators, keeping the low byte of what rand() returns. glibc
+    // is the TYPE_0 generator of random() (without the additive feedback of the
+    // default state size, and unlike rand_r which combines three steps), ansic
+    // the example of the C standard used by many libcs.
+    pub fn known() -> Vec<Lcg> {
+        vec![Lcg::new("msvc", 214013, 2531011, 1 << 32, 16),
+             Lcg::new("glibc", 1103515245, 12345, 1 << 31, 0),
+             Lcg::new("ansic", 1103515245, 12345, 1 << 31, 16),
+             Lcg::new("borland", 22695477, 1, 1 << 32, 16),
+             Lcg::new("minstd", 48271, 0, 2147483647, 0)]
+    }
+
+    // Generator by name or as "a,c,m,shift".
+    pub fn from_name(s : &str) -> Option<Lcg> {
+        if let Some(g) = Lcg::known().into_iter().find(|g| &g.name[..] == s) {
+            return Some(g);
+        }
+        let v : Vec<Option<u64>> = s.split(',').map(|e| e.parse().ok()).collect();
+        if v.len() != 4 || v.iter().any(|e| e.is_none()) {
+            return None;
+        }
+        let (a, c, m, shift) = (v[0].unwrap(), v[1].unwrap(), v[2].unwrap(), v[3].unwrap());
+        // Products have to fit in 64 bits unless m is a power of two.
+        if m < 2 || shift >= 64 || (m & (m - 1) != 0 && (a >= m || (m - 1) > (!0u64 - c) / m)) {
+            return None;
+        }
+        Some(Lcg::new(s, a, c, m, shift as usize))
+    }
+
+    fn mul_add(&self, x : u64, y : u64, z : u64) -> u64 {
+        // Wrapping is harmless modulo a power of two, which divides 2^64.
+        if self.m & (self.m - 1) == 0 {
+            x.wrapping_mul(y).wrapping_add(z) & (self.m - 1)
+        }
+        else {
+            (x * y + z) % self.m
+        }
+    }
+
+    pub fn step(&self, s : u64) -> u64 {
+        self.mul_add(self.a, s, self.c)
+    }
+
+    pub fn byte(&self, s : u64) -> u8 {
+        (s >> self.shift) as u8
+    }
+
+    // Number of seeds, the seeds are truncated to 32 bits like srand() does.
+    pub fn seeds(&self) -> u64 {
+        if self.m < 1 << 32 { self.m } else { 1 << 32 }
+    }
+
+    pub fn keystream(&self, seed : u64, n : usize) -> Vec<u8> {
+        let mut s = seed;
+        let mut k : Vec<u8> = Vec::with_capacity(n);
+        for _ in 0..n {
+            s = self.step(s);
+            k.push(self.byte(s));
+        }
+        k
+    }
+
+    // Generator whose single step is n steps of this one.
+    pub fn jump(&self, n : usize) -> Lcg {
+        let mut j = Lcg::new(&self.name[..], 1, 0, self.m, self.shift);
+        for _ in 0..n {
+            j.a = self.mul_add(self.a, j.a, 0);
+            j.c = self.step(j.c);
+        }
+        j
+    }
+}
+
+pub struct SeedMatch {
+    pub lcg : Lcg,
+    pub seed : u64,
+    pub score : f64
+}
+
+// Run f on slices of the seed range in parallel.
+fn search_seeds<T, F>(from : u64, to : u64, f : F) -> Vec<T>
+    where T : Send, F : Fn(u64, u64) -> T + Sync {
+    let threads = num_cpus() as u64;
+    let chunk = (to - from).div_ceil(threads);
+    let f = &f;
+    thread::scope(|s| {
+        let handles : Vec<_> = (0..threads).map(|t| {
+            let start = from + t * chunk;
+            let end = if start + chunk > to { to } else { start + chunk };
+            s.spawn(move || f(start, end))
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+// Whether the keystream starting at state s is the given one.
+fn stream_matches(lcg : &Lcg, s : u64, stream : &[u8]) -> bool {
+    let mut s = s;
+    for k in stream.iter() {
+        if lcg.byte(s) != *k {
+            return false;
+        }
+        s = lcg.step(s);
+    }
+    true
+}
+
+fn known_stream(data : &[u8], offset : usize, known : &[u8]) -> Vec<u8> {
+    (0..known.len()).map(|i| data[offset + i] ^ known[i]).collect()
+}
+
+// Seeds of the range whose keystream deciphers data[offset..] to the known
+// plaintext, at most limit of them.
+pub fn find_seeds_known(lcg : &Lcg, data : &[u8], offset : usize, known : &[u8], from : u64, to : u64,
+                        limit : usize) -> Vec<SeedMatch> {
+    let jump = lcg.jump(offset + 1);
+    let stream = known_stream(data, offset, known);
+    let stream = &stream[..];
+    let results = search_seeds(from, to, |start, end| {
+        let mut found : Vec<u64> = Vec::new();
+        for seed in start..end {
+            if stream_matches(lcg, jump.step(seed), stream) {
+                found.push(seed);
+                if found.len() >= limit {
+                    break;
+                }
+            }
+        }
+        found
+    });
+    let mut matches : Vec<SeedMatch> = Vec::new();
+    for seed in results.into_iter().flat_map(|r| r.into_iter()).take(limit) {
+        matches.push(SeedMatch {lcg : lcg.clone(), seed, score : 1f64});
+    }
+    matches
+}
+
+// Best seed of the range when scoring the first bytes of the deciphered data
+// against the sample.
+pub fn find_seed_stat(lcg : &Lcg, data : &[u8], sample : &Sample, scorer : &dyn Scorer, from : u64, to : u64)
+                      -> SeedMatch {
+    let n = if data.len() < STAT_BYTES { data.len() } else { STAT_BYTES };
+    let identity = identity_sub();
+    let identity = &identity;
+    let results = search_seeds(from, to, |start, end| {
+        let mut best = (start, f64::INFINITY);
+        for seed in start..end {
+            let mut freq = [0u32; 256];
+            let mut s = seed;
+            for i in 0..n {
+                s = lcg.step(s);
+                freq[(data[i] ^ lcg.byte(s)) as usize] += 1;
+            }
+            let mut u = [0f64; 256];
+            for v in 0usize..256 {
+                u[v] = freq[v] as f64 / n as f64;
+            }
+            let d = scorer.distance(&sample.unigram, &u, identity);
+            if d < best.1 {
+                best = (seed, d);
+            }
+        }
+        best
+    });
+    let mut best = results[0];
+    for r in results.iter() {
+        if r.1 < best.1 {
+            best = *r;
+        }
+    }
+    let plain = decipher(lcg, best.0, data);
+    SeedMatch {lcg : lcg.clone(), seed : best.0, score : scorer.score_plaintext(sample, &plain[..])}
+}
+
+// Inverse of an odd number modulo 2^64, each Newton iteration doubling the
+// number of correct low bits, x being its own inverse modulo 8.
+fn inverse_odd(x : u64) -> u64 {
+    let mut y = x;
+    for _ in 0..5 {
+        y = y.wrapping_mul(2u64.wrapping_sub(x.wrapping_mul(y)));
+    }
+    y
+}
+
+// Generators modulo a power of two whose key byte is s >> shift, and seeds
+// matching the known plaintext. The key stream only depends on the low
+// shift + 8 bits of the state, which form a generator of their own, so the
+// parameters are recovered modulo 2^(shift + 8): this covers custom generators
+// modulo 256 as well as modulo 2^31 or 2^32 with an output shift.
+// The hidden low bits of three consecutive states are brute forced, the
+// multiplier then solving a * (s1 - s0) = s2 - s1 and the increment
+// c = s1 - a * s0, and the candidates are checked against the rest of the known
+// plaintext. At least three known bytes are needed, a few more weed out the
+// wrong candidates.
+pub fn find_byte_lcgs(data : &[u8], offset : usize, known : &[u8], shift : usize, limit : usize)
+                      -> Vec<SeedMatch> {
+    if known.len() < 3 || shift > MAX_BYTE_SHIFT {
+        return Vec::new();
+    }
+    let stream = known_stream(data, offset, known);
+    let stream = &stream[..];
+    let bits = shift + 8;
+    let mask = (1u64 << bits) - 1;
+    let high : Vec<u64> = stream[..3].iter().map(|k| (*k as u64) << shift).collect();
+    let results = search_seeds(0, 1 << shift, |start, end| {
+        let mut found : Vec<(u64, u64)> = Vec::new();
+        for u0 in start..end {
+            let s0 = high[0] | u0;
+            for u1 in 0..1u64 << shift {
+                let s1 = high[1] | u1;
+                let d0 = s1.wrapping_sub(s0) & mask;
+                // a is only determined modulo 2^(bits - t) when 2^t divides
+                // s1 - s0, all its high bits are then tried.
+                let t = if d0 == 0 { bits } else { d0.trailing_zeros() as usize };
+                let inverse = inverse_odd(d0 >> t);
+                for u2 in 0..1u64 << shift {
+                    let d1 = (high[2] | u2).wrapping_sub(s1) & mask;
+                    if d1 & ((1 << t) - 1) != 0 {
+                        continue;
+                    }
+                    let a0 = (d1 >> t).wrapping_mul(inverse) & (mask >> t);
+                    for j in 0..1u64 << t {
+                        let a = a0 | j << (bits - t);
+                        let c = s1.wrapping_sub(a.wrapping_mul(s0)) & mask;
+                        if stream_matches(&Lcg::new("", a, c, 1 << bits, shift), s0, stream) {
+                            found.push((a, c));
+                            if found.len() >= limit {
+                                return found;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        found
+    });
+    let mut parameters : Vec<(u64, u64)> = Vec::new();
+    for p in results.into_iter().flat_map(|r| r.into_iter()) {
+        if !parameters.contains(&p) {
+            parameters.push(p);
+        }
+    }
+    let mut matches : Vec<SeedMatch> = Vec::new();
+    for (a, c) in parameters.into_iter() {
+        if matches.len() >= limit {
+            break;
+        }
+        let lcg = Lcg::new(&format!("{},{},{},{}", a, c, 1u64 << bits, shift)[..], a, c, 1 << bits, shift);
+        let left = limit - matches.len();
+        matches.extend(find_seeds_known(&lcg, data, offset, known, 0, 1 << bits, left));
+    }
+    matches
+}
+
+pub fn decipher(lcg : &Lcg, seed : u64, data : &[u8]) -> Vec<u8> {
+    let k = lcg.keystream(seed, data.len());
+    data.iter().zip(k.iter()).map(|(d, k)| *d ^ *k).collect()
+}
+
+// Known plaintext written as offset:hex.
+pub fn parse_known(s : &str) -> Option<(usize, Vec<u8>)> {
+    let i = s.find(':')?;
+    match (s[..i].parse().ok(), parse_hex(&s[i+1..])) {
+        (Some(offset), Ok(ref known)) if !known.is_empty() => Some((offset, known.clone())),
+        _ => None
+    }
+}
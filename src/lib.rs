@@ -0,0 +1,450 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::{Ordering, max};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+pub use crate::model::{CipherModel, auto_models, parse_model};
+pub use crate::scorer::{Metric, Scorer};
+use crate::scorer::rescale;
+use crate::model::reduced_models;
+use subcipher::period;
+
+pub mod analyze;
+pub mod batch;
+pub mod bench;
+pub mod cluster;
+pub mod constraint;
+pub mod filetype;
+pub mod lcg;
+pub mod model;
+pub mod passphrase;
+pub mod rng;
+pub mod scorer;
+pub mod segment;
+
+pub struct Probabilistic<T> {
+    pub p : f64,
+    pub v : T,
+}
+
+pub struct Sample {
+    pub data : Vec<u8>,
+    pub unigram : [f64; 256]
+}
+
+impl Default for Sample {
+    fn default() -> Sample {
+        Sample::new()
+    }
+}
+
+impl Sample {
+    pub fn new() -> Sample {
+        Sample {
+            data: Vec::new(),
+            unigram : [0f64; 256]
+        }
+    }
+
+    pub fn from_data(data : Vec<u8>) -> Sample {
+        let mut sample = Sample::new();
+        let freq = byte_counts(&data);
+        for (u, f) in sample.unigram.iter_mut().zip(freq.iter()) {
+            *u = *f as f64 / data.len() as f64;
+        }
+        sample.data = data;
+        sample
+    }
+}
+
+// Number of occurrences of each byte value.
+pub fn byte_counts(data : &[u8]) -> [u64; 256] {
+    let mut freq = [0u64; 256];
+    for b in data.iter() {
+        freq[*b as usize] += 1;
+    }
+    freq
+}
+
+// Identity substitution table.
+pub fn identity_sub() -> [usize; 256] {
+    let mut identity = [0usize; 256];
+    for (i, s) in identity.iter_mut().enumerate() {
+        *s = i;
+    }
+    identity
+}
+
+// Byte distribution of each column of the inputs, column p being made of the
+// bytes enciphered with key position p. The key starts at position 0 in every
+// input, inputs enciphered with the same key (a many-time pad) being pooled.
+// When the key restarts at the start of every block, the key position of byte
+// i is i % block % l. The segments of the inputs at the start of which the key
+// restarts are kept for the models whose key depends on the position in the
+// stream.
+pub struct Columns {
+    pub length : usize,
+    pub unigram : Vec<[f64; 256]>,
+    pub segments : Vec<Vec<u8>>
+}
+
+impl Columns {
+    pub fn new(inputs : &[&[u8]], l : usize, block : usize) -> Columns {
+        let segments = segments(inputs, block);
+        let mut unigram : Vec<[f64; 256]> = (0..l).map(|_| [0f64; 256]).collect();
+        for (p, u) in unigram.iter_mut().enumerate() {
+            let mut freq = [0u64; 256];
+            let mut sum = 0u64;
+            for segment in segments.iter() {
+                for b in segment.iter().skip(p).step_by(l) {
+                    sum += 1;
+                    freq[*b as usize] += 1;
+                }
+            }
+            for (v, f) in u.iter_mut().zip(freq.iter()) {
+                *v = *f as f64 / sum as f64;
+            }
+        }
+        Columns {length : l, unigram, segments : segments.iter().map(|s| s.to_vec()).collect()}
+    }
+}
+
+// Block size 0 means the key doesn't restart.
+fn block_length(len : usize, block : usize) -> usize {
+    if block == 0 || block > len {
+        max(len, 1)
+    }
+    else {
+        block
+    }
+}
+
+// Parts of the inputs at the start of which the key restarts.
+fn segments<'a>(inputs : &[&'a [u8]], block : usize) -> Vec<&'a [u8]> {
+    let mut segments : Vec<&[u8]> = Vec::new();
+    for data in inputs.iter() {
+        segments.extend(data.chunks(block_length(data.len(), block)));
+    }
+    segments
+}
+
+// Run the search of each column in its own thread and collect the results in
+// column order. The search gets the column number and its distribution.
+pub fn search_columns<T, F>(columns : &Columns, search : F) -> Vec<T>
+    where T : Send, F : Fn(usize, &[f64; 256]) -> T + Sync {
+    let search = &search;
+    thread::scope(|s| {
+        let handles : Vec<_> = columns.unigram.iter().enumerate().map(|(p, u)| {
+            s.spawn(move || search(p, u))
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+// Number of threads to run, the number of CPUs.
+pub fn num_cpus() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// Mean score of the columns. An infinite distance means the search of a column
+// was aborted and gives a score of 0.
+pub fn mean_similarity(scorer : &dyn Scorer, sample : &Sample, distances : &[f64]) -> f64 {
+    if distances.contains(&f64::INFINITY) {
+        return 0f64;
+    }
+    let l = distances.len() as f64;
+    let random = scorer.random_similarity(sample);
+    distances.iter().fold(0f64, |a, &v| a + rescale(scorer.similarity(v), random) / l)
+}
+
+// Score penalty per key bit when comparing different models. A more complex
+// model can always fit the data at least as well as a simpler one it includes,
+// so it has to beat it by a margin to be selected.
+pub const COMPLEXITY_PENALTY : f64 = 0.0005;
+
+#[derive(Clone)]
+pub struct Candidate {
+    pub score : f64,
+    pub length : usize,
+    pub model : Box<dyn CipherModel>,
+    pub key : Vec<Vec<u8>>
+}
+
+// Break the inputs for each length candidate. Keys are normalised and candidates
+// producing the same substitutions as a previous one are dropped. Aborted
+// candidates are kept with a score of 0 and their raw key.
+pub fn crack_model(model : &dyn CipherModel, inputs : &[&[u8]], sample : &Sample, scorer : &dyn Scorer,
+                   length : &[Probabilistic<usize>], block : usize) -> Vec<Candidate> {
+    let mut candidates : Vec<Candidate> = Vec::new();
+    for l in length.iter() {
+        let mut key : Vec<Vec<u8>> = Vec::new();
+        let score = model.break_key(&Columns::new(inputs, l.v, block), sample, scorer, &mut key);
+        if score == 0f64 {
+            candidates.push(Candidate {score : 0f64, length : l.v, model : model.box_clone(), key});
+            continue;
+        }
+        let (nm, nkey) = normalize_key(model, &key);
+        // Keys which only differ by their period or by equivalent
+        // components produce the same substitution tables.
+        if candidates.iter().any(|c| c.score != 0f64 && same_subs(&*c.model, &c.key, &*nm, &nkey)) {
+            continue;
+        }
+        candidates.push(Candidate {score, length : nm.key_length(&nkey), model : nm, key : nkey});
+    }
+    candidates
+}
+
+// Best candidate once penalised by the complexity of its model.
+pub fn best_candidate(candidates : &[Candidate]) -> Option<&Candidate> {
+    let mut best : Option<&Candidate> = None;
+    for c in candidates.iter() {
+        if c.score == 0f64 {
+            continue;
+        }
+        best = match best {
+            Some(b) if b.score - COMPLEXITY_PENALTY * b.model.key_bits() >=
+                       c.score - COMPLEXITY_PENALTY * c.model.key_bits() => Some(b),
+            _ => Some(c)
+        };
+    }
+    best
+}
+
+// Scores are bounded by 1, a model can't beat the best candidate so far if the
+// penalty of its complexity is too high.
+pub fn model_is_hopeless(model : &dyn CipherModel, best : Option<&Candidate>) -> bool {
+    match best {
+        Some(b) => b.score - COMPLEXITY_PENALTY * b.model.key_bits() >=
+                   1f64 - COMPLEXITY_PENALTY * model.key_bits(),
+        None => false
+    }
+}
+
+// Guess the key length and break the inputs with the best of the models.
+pub fn crack(models : &[Box<dyn CipherModel>], inputs : &[&[u8]], sample : &Sample, scorer : &dyn Scorer,
+             max_length : usize, block : usize) -> Option<Candidate> {
+    let mut length : Vec<Probabilistic<usize>> = Vec::new();
+    let mut candidates : Vec<Candidate> = Vec::new();
+    find_length_candidates(inputs, &mut length, max_length, block);
+    length.truncate(5);
+    for m in models.iter() {
+        if model_is_hopeless(&**m, best_candidate(&candidates)) {
+            continue;
+        }
+        candidates.extend(crack_model(&**m, inputs, sample, scorer, &length, block));
+    }
+    best_candidate(&candidates).cloned()
+}
+
+pub fn find_length_candidates(inputs : &[&[u8]], length : &mut Vec<Probabilistic<usize>>, max_l : usize,
+                              block : usize) {
+    let segments = segments(inputs, block);
+    for l in 1..max_l+1 {
+        length.push(Probabilistic{ p : 0f64, v : l});
+        for p in 0..l {
+            let mut freq = [0u64; 256];
+            let mut sum = 0u64;
+            let mut var = 0f64;
+            for segment in segments.iter() {
+                for b in segment.iter().skip(p).step_by(l) {
+                    freq[*b as usize] += 1u64;
+                    sum += 1u64;
+                }
+            }
+            for f in freq.iter() {
+                let diff = (*f as f64 / sum as f64)-(1f64/256f64);
+                var += diff*diff;
+            }
+            length[l-1].p += var.sqrt() / (l as f64).powf(1.1);
+        }
+    }
+    length.sort_by(|a, b| b.p.partial_cmp(&a.p).unwrap_or(Ordering::Equal));
+}
+
+// Block sizes tried by the automatic detection.
+pub const BLOCK_CANDIDATES : [usize; 13] = [16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384,
+                                            32768, 65536];
+
+// Factor by which the best length candidate has to improve for a block size
+// to be detected.
+const BLOCK_MARGIN : f64 = 1.1;
+
+// Guess the size of the blocks at the start of which the key restarts, 0 if it
+// doesn't. Unless the block size is a multiple of the key length, restarting
+// the key scatters the key positions, so the best length candidate stands out
+// much more once the positions are remapped with the right block size.
+pub fn find_block_size(inputs : &[&[u8]], max_l : usize) -> usize {
+    let best_length = |block| {
+        let mut length : Vec<Probabilistic<usize>> = Vec::new();
+        find_length_candidates(inputs, &mut length, max_l, block);
+        length[0].p
+    };
+    let mut best = (0usize, best_length(0) * BLOCK_MARGIN);
+    for &b in BLOCK_CANDIDATES.iter() {
+        if inputs.iter().all(|data| 2 * b > data.len()) {
+            break;
+        }
+        let p = best_length(b);
+        if p > best.1 {
+            best = (b, p);
+        }
+    }
+    best.0
+}
+
+// Split a "path:weight" sample specification. A path without a valid weight
+// suffix has a weight of 1.
+pub fn parse_sample_spec(spec : &str) -> Option<(String, f64)> {
+    match spec.rfind(':') {
+        Some(i) => {
+            match spec[i+1..].parse::<f64>() {
+                Ok(w) if w > 0f64 => Some((spec[..i].to_string(), w)),
+                Ok(_) => None,
+                Err(_) => Some((spec.to_string(), 1f64))
+            }
+        },
+        None => Some((spec.to_string(), 1f64))
+    }
+}
+
+// Files of a directory and of its subdirectories.
+pub fn walk_files(dir : &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files : Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(walk_files(&path)?);
+        }
+        else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+// Output file of each input in the directory, named after the input. Inputs
+// with the same name get a numbered suffix.
+pub fn output_paths(files : &[PathBuf], dir : &Path) -> Vec<PathBuf> {
+    let mut outputs : Vec<PathBuf> = Vec::new();
+    for f in files.iter() {
+        let name = f.file_name().map_or("output".into(), |n| n.to_os_string());
+        let mut out = dir.join(&name);
+        let mut n = 1usize;
+        while outputs.contains(&out) {
+            let mut numbered = name.clone();
+            numbered.push(format!(".{}", n));
+            out = dir.join(&numbered);
+            n += 1;
+        }
+        outputs.push(out);
+    }
+    outputs
+}
+
+fn read_sample_data(path : &str) -> Result<Vec<u8>, String> {
+    if path == "-" {
+        let mut d : Vec<u8> = Vec::new();
+        return match io::stdin().read_to_end(&mut d) {
+            Ok(_) => Ok(d),
+            Err(e) => Err(format!("Could not read sample from stdin: {}", e))
+        };
+    }
+    let p = Path::new(path);
+    if p.is_dir() {
+        let mut data : Vec<u8> = Vec::new();
+        let files = match walk_files(p) {
+            Ok(f) => f,
+            Err(e) => return Err(format!("Could not read sample directory {}: {}", path, e))
+        };
+        for f in files {
+            match fs::read(&f) {
+                Ok(d) => data.extend_from_slice(&d),
+                Err(e) => return Err(format!("Could not read sample file {}: {}", f.display(), e))
+            }
+        }
+        return Ok(data);
+    }
+    match fs::read(p) {
+        Ok(d) => Ok(d),
+        Err(e) => Err(format!("Could not read sample file {}: {}", path, e))
+    }
+}
+
+// Load the weighted samples, failing on the first one which can't be read or is
+// empty, like stdin given twice.
+pub fn read_sample(specs : &[(String, f64)], sample : &mut Sample) -> Result<(), String>
+{
+    let mut total = 0f64;
+    sample.data.clear();
+    sample.unigram = [0f64; 256];
+    for (path, w) in specs.iter() {
+        let data = read_sample_data(path)?;
+        if data.is_empty() {
+            return Err(format!("Sample {} is empty", path));
+        }
+        let freq = byte_counts(&data);
+        for (u, f) in sample.unigram.iter_mut().zip(freq.iter()) {
+            *u += w * *f as f64 / data.len() as f64;
+        }
+        total += w;
+        sample.data.extend_from_slice(&data);
+    }
+    for u in sample.unigram.iter_mut() {
+        *u /= total;
+    }
+    Ok(())
+}
+
+// Decipher the data with a key of the model, restarting the key at every block.
+pub fn decipher(model : &dyn CipherModel, key : &[Vec<u8>], data : &[u8], block : usize) -> Vec<u8> {
+    let mut plain : Vec<u8> = Vec::with_capacity(data.len());
+    for chunk in data.chunks(block_length(data.len(), block)) {
+        plain.extend_from_slice(&model.decipher(key, chunk));
+    }
+    plain
+}
+
+pub fn same_subs(m1 : &dyn CipherModel, k1 : &[Vec<u8>], m2 : &dyn CipherModel, k2 : &[Vec<u8>]) -> bool {
+    let s1 = m1.gen_key_subs(k1);
+    let s2 = m2.gen_key_subs(k2);
+    s1.len() == s2.len() && (0..s1.len()).all(|p| s1[p][..] == s2[p][..])
+}
+
+// Reduce a key to its shortest period and to the simplest model producing the
+// same substitution tables, unless that would make the key longer or the model
+// isn't a substitution.
+pub fn normalize_key(model : &dyn CipherModel, key : &[Vec<u8>]) -> (Box<dyn CipherModel>, Vec<Vec<u8>>) {
+    let subs = model.gen_key_subs(key);
+    let period = period(&subs);
+    for r in reduced_models().into_iter() {
+        if !model.is_substitution() || r.key_bits() > model.key_bits() ||
+           period > model.key_length(key) {
+            break;
+        }
+        if let Some(k) = r.find_key(&subs[..period]) {
+            let k = r.truncate_key(&k, period);
+            return (r, k);
+        }
+    }
+    (model.box_clone(), model.truncate_key(key, period))
+}
@@ -17,146 +17,432 @@ along with this program.  If not, see <http://www.gnu.org/licenses/>.
 Copyright 2014 Charles Hubain <github@haxelion.eu>
 */
 
-use std::cmp::Ordering;
-use std::io::File;
-use std::iter::repeat;
-use std::iter::IteratorExt;
-use std::num::Float;
-use std::os;
-use std::sync::mpsc::channel;
-use std::thread::Thread;
-
-struct Probabilistic<T> {
-    p : f64,
-    v : T,
-}
-
-struct Sample {
-    data : Vec<u8>,
-    unigram : [f64; 256]
-}
-
-impl Sample {
-    fn new() -> Sample {
-        Sample {
-            data: Vec::new(),
-            unigram : [0f64; 256]
-        }
-    }
-}
-
-enum Model {Level1, Level2, Level3, Level4}
-
-struct SBTask {
-    x : u8,
-    a : u8,
-    m : u16,
-    p : usize,
-    score : f64
-}
+use std::cmp::min;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use subbuster::{Candidate, CipherModel, Columns, Metric, Probabilistic, Sample, Scorer};
+use subbuster::{best_candidate, crack_model, find_block_size, find_length_candidates, model_is_hopeless};
+use subbuster::{decipher, num_cpus, output_paths, parse_sample_spec, read_sample};
+use subbuster::analyze::{DEFAULT_WINDOW, entropy_map, regions, reliability_threshold, unlikely_substitution};
+use subbuster::batch::{FileResult, crack_files, expand_inputs, read_file_list};
+use subbuster::bench::{BenchConfig, recovery_rate, run_bench};
+use subbuster::cluster::cluster_keys;
+use subbuster::filetype::{HEADER_BYTES, MAGIC_BONUS, boost_candidates, describe, has_magic};
+use subbuster::lcg::{DEFAULT_STAT_SEEDS, Lcg, MAX_BYTE_SHIFT, SeedMatch, find_byte_lcgs, find_seed_stat, find_seeds_known, parse_known};
+use subbuster::model::{XorModel, auto_models, constrained_model, extend_length, parse_model};
+use subbuster::passphrase::{complete_key, key_similarities, key_text, low_confidence, parse_wordlist};
+use subbuster::segment::{DEFAULT_SEGMENT_WINDOW, find_segments};
 
 fn print_usage() {
-    println!("subbuster [-m [1|2|3]] [-l l] [-k k] [-v] input sample");
-    println!("");
+    println!("subbuster [-m [1|2|3|auto|ops]] [-l l] [-k k] [--block-size [b|auto]] ");
+    println!("          [--fix c=key]... [--range c=range[,range]...]... [--text] [-w wordlist] ");
+    println!("          [--magic] [--calibrate] [--metric metric] [-v] [-o output] input ");
+    println!("          [-i input]... sample[:weight]...");
+    println!("subbuster bench [-m m[,m]...] [-l l[,l]...] [-s s[,s]...] [-n n] [--min-rate r] ");
+    println!("          [--metric metric] corpus [sample[:weight]...]");
+    println!("subbuster analyze [-w window] [-k k] [--metric metric] [-v] input [sample[:weight]...]");
+    println!("subbuster batch [-m [1|2|3|auto|ops]] [-k k] [--block-size b] [--metric metric] ");
+    println!("          [-j jobs] [-f list] [-o directory] -s sample[:weight]... input...");
+    println!("subbuster segment [-m [1|2|3|auto|ops]] [-k k] [-w window] [--metric metric] ");
+    println!("          [-o output] input sample[:weight]...");
+    println!("subbuster cluster [-m [1|2|3|auto|ops]] [-k k] [--block-size b] [--metric metric] ");
+    println!("          directory sample[:weight]...");
+    println!("subbuster lcg [-g gen] [-p offset:hex] [--seeds from-to] [--metric metric] ");
+    println!("          [-o output] input [sample[:weight]...]");
+    println!();
     println!("* input: input file to decipher.");
+    println!("* -i: another input enciphered with the same key, starting at key position 0. ");
+    println!("The inputs are broken together and all deciphered.");
     println!("* sample: some plaintext sample from which byte the frequency distribution is ");
-    println!("computed.");
+    println!("computed. A sample can be a file, a directory whose files are all used or - ");
+    println!("to read it from stdin. When several samples are given, the distribution is ");
+    println!("their mixture, each sample counting for its optional weight (default to 1).");
     println!("* -m: optional model level number, default to 1. Model level 1 is xor, model ");
-    println!("level 2 is xor-add, model level 3 is xor-add-mix.");
+    println!("level 2 is xor-add, model level 3 is xor-add-mix. auto tries the models in ");
+    println!("increasing complexity and reports the one which best explains the input.");
+    println!("A model can also be a sequence of operations among xor, add, sub, rol, mix, ");
+    println!("mul (by an odd number) and nxor (xor skipping the bytes equal to 00 or to ");
+    println!("the key) separated by commas or dashes, like rol,xor or ");
+    println!("xor-mul, which are applied in order to each plaintext byte. Their key space ");
+    println!("is searched exhaustively, a single mix being allowed. affine is mul-add.");
+    println!("A single operation followed by inc or idx, like xor-inc, is a stream model: ");
+    println!("the key byte at position i is the repeating key byte plus (inc) or xored with ");
+    println!("(idx) i*d, d being searched too. Only the first 8192 bytes are analysed.");
+    println!("Word models like xor32, add32le, add16be or sub64le xor, add or subtract ");
+    println!("whole little (default) or big endian words with carries, the key length is ");
+    println!("rounded up to a whole number of words.");
     println!("* -l: optional key length. If not provided, subbuster attempts to guess the key ");
     println!("length using entropy.");
     println!("* -k: optional maximum key length, default to 10.");
+    println!("* --block-size: optional size of the blocks at the start of which the key ");
+    println!("restarts (per sector or record), the key position of byte i being then ");
+    println!("i % b % l. auto tries the powers of two from 16 to 65536.");
+    println!("* --fix: known key of the component with letter c (x, a, m, ... followed by ");
+    println!("its occurrence number if the model has several, like x2), a hex string with ");
+    println!("?? for the unknown bytes like --fix a=de??beef. Mixes can use the list ");
+    println!("notation with [?]. The fixed key repeats with its own length, the key length ");
+    println!("being extended to a multiple of it.");
+    println!("* --range: allowed keys of the component at each position, like 20-7e or ");
+    println!("printable, a single range applying to every position. Only the allowed keys ");
+    println!("are searched, levels being searched as their sequence of operations.");
+    println!("* --text: the xor key is a passphrase made of printable characters, it is ");
+    println!("printed as text with its low confidence positions.");
+    println!("* -w: wordlist, one word or phrase per line, whose entries or pairs of ");
+    println!("entries best completing the passphrase are proposed. Implies --text.");
+    println!("* --magic: favour the keys whose plaintext starts with a valid header of a ");
+    println!("known file type (PE, ELF, Zip, PDF, gzip, PNG, script or HTML).");
+    println!("* --calibrate: break random bytes of the input size with the best model and key ");
+    println!("length and warn when the best key doesn't beat their score by a margin.");
+    println!("* --metric: optional distance between byte distributions, one of l2 ");
+    println!("(default), chi2, kl (Kullback-Leibler divergence) and bhattacharyya. Scores ");
+    println!("are between 0 and 1 for all metrics.");
     println!("* -v: verbose mode, display the results from all the candidates.");
-    println!("");
+    println!("The type of the plaintext, like file reports it, is printed with the best key. ");
+    println!("A warning is printed when the input looks like random bytes, and with ");
+    println!("--calibrate when the score of the best key is too close to the one of random ");
+    println!("bytes.");
+    println!("* -o: optional output file where the input deciphered with the best key is ");
+    println!("written, or directory where each input is written if there are several.");
+    println!();
+    println!("The bench command encrypts slices of the corpus with random keys, cracks them ");
+    println!("and reports the key recovery rate for each model, key length and size. The ");
+    println!("corpus, which can't be empty, is used as sample if none is given. Lengths, ");
+    println!("sizes and the number of trials have to be at least 1.");
+    println!("* -m: models to bench, default to 1,2. Operation sequences have to use dashes.");
+    println!("* -l: key lengths, default to 1,2,3,4,8.");
+    println!("* -s: ciphertext sizes, default to 1024,4096,16384.");
+    println!("* -n: number of trials for each model, length and size, default to 5.");
+    println!("* --min-rate: exit with an error if the recovery rate of a model is lower, ");
+    println!("default to 0.9.");
+    println!();
+    println!("The analyze command splits the input in windows, computes their entropy and ");
+    println!("periodicity and reports the regions which look like plaintext, substitution ");
+    println!("enciphered data (with their key length), compressed or strongly encrypted ");
+    println!("data, or padding. With samples, plaintext is told apart from single byte keys.");
+    println!("* -w: window size, at least 256, default to 4096.");
+    println!("* -k: maximum key length, default to 10.");
+    println!("* -v: print the entropy map of the windows.");
+    println!();
+    println!("The batch command breaks many inputs in parallel with the same samples and ");
+    println!("prints a table of the results. -m, -k, --block-size and --metric are the same ");
+    println!("as above.");
+    println!("* input: file, directory whose files are all broken or pattern with * and ? ");
+    println!("in the file name like dumps/*.bin.");
+    println!("* -s: sample, at least one is required.");
+    println!("* -f: file listing inputs, one per line.");
+    println!("* -j: number of inputs broken at the same time, default to the number of CPUs.");
+    println!("* -o: optional directory where the deciphered inputs are written.");
+    println!();
+    println!("The segment command detects where the key changes along the input, breaks ");
+    println!("each segment separately and reports their boundaries and keys.");
+    println!("* -w: size of the windows whose keys are compared, default to 1024.");
+    println!("* -o: optional output file where the input deciphered segment by segment is ");
+    println!("written.");
+    println!();
+    println!("The cluster command breaks each file of the directory and groups the files ");
+    println!("whose keys are the same up to a rotation, the phase of a file being the key ");
+    println!("position at which it starts. -m, -k, --block-size and --metric are the same ");
+    println!("as above.");
+    println!();
+    println!("The lcg command breaks a xor with the low byte of a linear congruential ");
+    println!("generator output, s = (a * s + c) mod m, by brute forcing its seed.");
+    println!("* -g: generator among msvc, glibc, ansic, borland and minstd, or a,c,m,shift ");
+    println!("for a custom one giving the byte s >> shift. byte[:shift] recovers the ");
+    println!("parameters of a generator modulo a power of two from the known plaintext, ");
+    println!("shift defaulting to 0 and being at most 10. All the well-known generators ");
+    println!("are tried by default.");
+    println!("* -p: known plaintext at the given offset, like 0:4d5a90. Without it the ");
+    println!("seeds are scored with the samples.");
+    println!("* --seeds: range of seeds to try, both bounds included, default to all the 32 ");
+    println!("bits seeds with a known plaintext and to the first 2^24 ones otherwise.");
+    println!("* -o: optional output file where the input deciphered with the best seed is ");
+    println!("written.");
+    println!();
     println!("Warning: model level 3 is really slow because of the large key space ");
     println!("(2 642 411 520 key possibilites per byte). It is optimized to find solutions");
     println!("with high score and will abort if the solutions are too bad. ");
 }
 
 fn main() {
-    let mut args: Vec<String> = os::args();
-    let mut sample = Sample::new();
+    let args: Vec<String> = env::args().collect();
     let mut length: Vec<Probabilistic<usize>> = Vec::new();
     let mut verbose = false;
-    let mut model = Model::Level1;
-    let mut max_length = 10us;
+    let mut models : Vec<Box<dyn CipherModel>> = vec![Box::new(XorModel) as Box<dyn CipherModel>];
+    let mut auto = false;
+    let mut output : Option<String> = None;
+    let mut inputs : Vec<String> = Vec::new();
+    let mut model_name = "1".to_string();
+    let mut fixed : Vec<String> = Vec::new();
+    let mut ranges : Vec<String> = Vec::new();
+    let mut text = false;
+    let mut wordlist : Option<String> = None;
+    let mut magic = false;
+    let mut calibrate = false;
+    let mut max_length = 10usize;
+    let mut block = 0usize;
+    let mut auto_block = false;
+    let mut metric = Metric::L2;
     let mut i : usize;
 
     if args.len() < 3 {
         print_usage();
         return;
     }
-    let sample_path = args.pop().unwrap();
-    let input = args.pop().unwrap();
-    i = 0;
+    if &args[1][..] == "bench" {
+        bench(&args[2..]);
+        return;
+    }
+    if &args[1][..] == "lcg" {
+        lcg(&args[2..]);
+        return;
+    }
+    if &args[1][..] == "analyze" {
+        analyze(&args[2..]);
+        return;
+    }
+    if &args[1][..] == "batch" {
+        batch(&args[2..]);
+        return;
+    }
+    if &args[1][..] == "segment" {
+        segment(&args[2..]);
+        return;
+    }
+    if &args[1][..] == "cluster" {
+        cluster(&args[2..]);
+        return;
+    }
+    let mut positional : Vec<String> = Vec::new();
+    i = 1;
     while i < args.len() {
-        if &args[i][] == "-k" {
+        if &args[i][..] == "-k" {
             i += 1;
             if i >= args.len() {
-                println!("No maximum key length given");
+                eprintln!("No maximum key length given");
                 print_usage();
                 return;
             }
-            max_length = match args[i][].parse() {
+            max_length = match parse_max_length(&args[i][..]) {
                 Some(m) => { m },
                 None => {
-                    println!("{} is not a valid maximum key length", args[i]);
+                    eprintln!("{} is not a valid maximum key length", args[i]);
                     print_usage();
                     return;
                 }
             }
         }
-        else if &args[i][] == "-v" {
-            verbose = true;
+        else if &args[i][..] == "--block-size" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("No block size given");
+                print_usage();
+                return;
+            }
+            auto_block = &args[i][..] == "auto";
+            if !auto_block {
+                block = match args[i][..].parse().ok() {
+                    Some(b) if b > 0 => b,
+                    _ => {
+                        eprintln!("{} is not a valid block size", args[i]);
+                        print_usage();
+                        return;
+                    }
+                };
+            }
         }
-        else if &args[i][] == "-m" {
+        else if &args[i][..] == "--metric" {
             i += 1;
             if i >= args.len() {
-                println!("No model level number given");
+                eprintln!("No metric given");
                 print_usage();
                 return;
             }
-            model = match args[i].as_slice().parse() {
-                Some(1) => Model::Level1,
-                Some(2) => Model::Level2,
-                Some(3) => Model::Level3,
-                Some(4) => Model::Level4,
-                _ => {
-                    println!("{} is not a valid model level", args[i]);
+            metric = match Metric::from_name(&args[i][..]) {
+                Some(m) => m,
+                None => {
+                    eprintln!("{} is not a valid metric", args[i]);
                     print_usage();
                     return;
                 }
             };
         }
-        else if &args[i][] == "-l" {
+        else if &args[i][..] == "-v" {
+            verbose = true;
+        }
+        else if &args[i][..] == "-m" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("No model level number given");
+                print_usage();
+                return;
+            }
+            model_name = args[i].clone();
+            auto = &args[i][..] == "auto";
+            models = load_models(&args[i][..]);
+        }
+        else if &args[i][..] == "--fix" || &args[i][..] == "--range" {
             i += 1;
             if i >= args.len() {
-                println!("No key length given");
+                eprintln!("No constraint given for {}", args[i-1]);
                 print_usage();
                 return;
             }
-            match args[i][].parse() {
+            if &args[i-1][..] == "--fix" {
+                fixed.push(args[i].clone());
+            }
+            else {
+                ranges.push(args[i].clone());
+            }
+        }
+        else if &args[i][..] == "--text" {
+            text = true;
+        }
+        else if &args[i][..] == "--magic" {
+            magic = true;
+        }
+        else if &args[i][..] == "--calibrate" {
+            calibrate = true;
+        }
+        else if &args[i][..] == "-w" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("No wordlist given");
+                print_usage();
+                return;
+            }
+            wordlist = Some(args[i].clone());
+            text = true;
+        }
+        else if &args[i][..] == "-o" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("No output file given");
+                print_usage();
+                return;
+            }
+            output = Some(args[i].clone());
+        }
+        else if &args[i][..] == "-i" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("No input file given");
+                print_usage();
+                return;
+            }
+            inputs.push(args[i].clone());
+        }
+        else if &args[i][..] == "-l" {
+            i += 1;
+            if i >= args.len() {
+                eprintln!("No key length given");
+                print_usage();
+                return;
+            }
+            match args[i][..].parse().ok() {
                 Some(l) => {
                     length.push(Probabilistic {p : 1f64, v : l});
                 },
                 None => {
-                    println!("{} is not a valid key length", args[i]);
+                    eprintln!("{} is not a valid key length", args[i]);
                     print_usage();
                     return;
                 }
             }
         }
+        else {
+            positional.push(args[i].clone());
+        }
         i += 1;
     }
 
-    read_sample(sample_path.as_slice(), &mut sample);
-    let data  = match File::open(&Path::new(&input[])).read_to_end() {
-        Ok(d) => { d },
-        Err(e) => {println!("Could not read input file: {}", e); return;}
+    if text {
+        if &model_name[..] != "1" && &model_name[..] != "xor" {
+            eprintln!("Passphrase keys can only be searched with the xor model");
+            return;
+        }
+        ranges.push("x=printable".to_string());
+    }
+    if !fixed.is_empty() || !ranges.is_empty() {
+        if auto {
+            eprintln!("Constraints can't be used with the automatic model selection");
+            return;
+        }
+        models = match constrained_model(&model_name[..], &fixed[..], &ranges[..]) {
+            Ok(m) => vec![m],
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let period = models[0].key_period();
+        for l in length.iter_mut() {
+            let e = extend_length(l.v, period);
+            if e != l.v {
+                println!("The key length {} is extended to {}, the constraints repeating every {} bytes.",
+                         l.v, e, period);
+                l.v = e;
+            }
+        }
+    }
+
+    if positional.len() < 2 {
+        eprintln!("An input and at least one sample are required");
+        print_usage();
+        return;
+    }
+    inputs.insert(0, positional.remove(0));
+    let sample = load_sample(&positional[..]);
+
+    let mut words : Vec<Vec<u8>> = Vec::new();
+    if let Some(ref path) = wordlist {
+        words = match fs::read(path) {
+            Ok(d) => parse_wordlist(&d[..]),
+            Err(e) => {eprintln!("Could not read wordlist: {}", e); return;}
+        };
+    }
+
+    let mut data : Vec<Vec<u8>> = Vec::new();
+    for input in inputs.iter() {
+        match fs::read(input) {
+            Ok(d) => data.push(d),
+            Err(e) => {eprintln!("Could not read input file {}: {}", input, e); return;}
+        }
+    }
+    let slices : Vec<&[u8]> = data.iter().map(|d| &d[..]).collect();
+    // Several inputs are written in the output directory, which is created
+    // before breaking them.
+    let outputs : Vec<PathBuf> = match output {
+        Some(ref path) if inputs.len() == 1 => vec![PathBuf::from(path)],
+        Some(ref dir) => {
+            let dir = Path::new(dir);
+            if let Err(e) = fs::create_dir_all(dir) {
+                eprintln!("Could not create output directory {}: {}", dir.display(), e);
+                return;
+            }
+            let files : Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+            output_paths(&files[..], dir)
+        },
+        None => Vec::new()
     };
+    if slices.iter().all(|d| unlikely_substitution(d, max_length)) {
+        println!("Warning: the input is close to random bytes, like compressed or strongly encrypted ");
+        println!("data, it is unlikely to be enciphered with a byte substitution.");
+    }
+
+    if auto_block {
+        block = find_block_size(&slices[..], max_length);
+        if block == 0 {
+            println!("No block size detected.");
+        }
+    }
+    if block != 0 {
+        println!("Block size: {}", block);
+    }
 
     if length.is_empty() {
-        find_length_candidates(&data[], &mut length, max_length);
+        find_length_candidates(&slices[..], &mut length, max_length, block);
         if verbose {
             println!("Length candidates: ");
             println!("------------------\n");
@@ -168,411 +454,729 @@ fn main() {
         }
     }
 
-    let mut best_score = 0f64;
-    let mut best_key : Vec<Vec<u8>> = Vec::new();
     length.truncate(5);
-    if verbose {
-        println!("Key candidates:");
-        println!("---------------\n");
-        println!("S        | l   | K");
-    }
-    for l in length.iter() {
-        let mut key : Vec<Vec<u8>> = Vec::new();
-        let score = match model {
-            Model::Level1 => break_lvl1(&data[], &sample, l.v, &mut key),
-            Model::Level2 => break_lvl2(&data[], &sample, l.v, &mut key),
-            Model::Level3 => break_lvl3(&data[], &sample, l.v, &mut key),
-            Model::Level4 => break_lvl4(&data[], &sample, l.v, &mut key),
-        };
-        if score > best_score {
-            best_key = key.clone();
-            best_score = score;
+    let mut candidates : Vec<Candidate> = Vec::new();
+    for m in models.iter() {
+        if model_is_hopeless(&**m, best_candidate(&candidates[..])) {
+            if verbose {
+                println!("Skipping model {}: it can't beat {}.\n", m.name(),
+                         best_candidate(&candidates[..]).unwrap().model.name());
+            }
+            continue;
         }
         if verbose {
-            if score == 0f64 {
-                print!("ABORTED  : {:3} : ", l.v);
+            if auto {
+                println!("Key candidates ({}):", m.name());
             }
             else {
-                print!("{:.6} : {:3} : ", score, l.v);
+                println!("Key candidates:");
             }
-            print_key(&key);
-            print!("\n");
+            println!("---------------\n");
+            println!("S        | l   | K");
         }
+        let mut model_candidates = crack_model(&**m, &slices[..], &sample, &metric, &length[..], block);
+        if magic {
+            boost_candidates(&mut model_candidates[..], slices[0], block);
+        }
+        if verbose {
+            for c in model_candidates.iter() {
+                if c.score == 0f64 {
+                    print!("ABORTED  : {:3} : ", c.length);
+                }
+                else {
+                    print!("{:.6} : {:3} : ", c.score, c.length);
+                }
+                print!("{}", c.model.format_key(&c.key));
+                if c.model.name() != m.name() {
+                    print!(" ({})", c.model.name());
+                }
+                println!();
+            }
+            println!();
+        }
+        candidates.extend(model_candidates);
     }
 
-    if verbose {
-        print!("\n");
+    match best_candidate(&candidates[..]) {
+        Some(best) => {
+            if auto {
+                println!("Best model: {}", best.model.name());
+            }
+            println!("Best key: {:.6} : {:3} : {}", best.score, best.length, best.model.format_key(&best.key));
+            // Breaking random bytes as big as the input is as slow as breaking
+            // the input, so the threshold is only computed when asked.
+            if calibrate {
+                let size = slices.iter().fold(0usize, |a, d| a + d.len());
+                let threshold = reliability_threshold(&*best.model, size, best.length, block, &sample, &metric);
+                // The bonus of --magic isn't part of the statistical score.
+                let score = if magic && has_magic(best, slices[0], block) { best.score - MAGIC_BONUS } else { best.score };
+                if score < threshold {
+                    println!("Warning: the score is lower than {:.6}, the score of random bytes plus a margin, ",
+                             threshold);
+                    println!("the key is unreliable.");
+                }
+            }
+            for (input, d) in inputs.iter().zip(slices.iter()) {
+                let header = &d[..min(d.len(), HEADER_BYTES)];
+                let file_type = describe(&decipher(&*best.model, &best.key, header, block)[..]);
+                if inputs.len() == 1 {
+                    println!("File type: {}", file_type);
+                }
+                else {
+                    println!("File type of {}: {}", input, file_type);
+                }
+            }
+            if text {
+                print_passphrase(&best.key[0][..], &slices[..], &sample, &metric, block, &words[..]);
+            }
+            for (out, d) in outputs.iter().zip(slices.iter()) {
+                let plain = decipher(&*best.model, &best.key, d, block);
+                if let Err(e) = fs::write(out, &plain) {
+                    eprintln!("Could not write output file {}: {}", out.display(), e);
+                }
+            }
+        },
+        None => {
+            println!("No key found.");
+        }
     }
+}
 
-    if best_score != 0f64 {
-        print!("Best key: {:.6} : {:3} : ", best_score, best_key[0].len());
-        print_key(&best_key);
-        print!("\n");
+// Print the passphrase with its low confidence positions and the wordlist
+// entries completing it.
+fn print_passphrase(key : &[u8], inputs : &[&[u8]], sample : &Sample, metric : &Metric, block : usize,
+                    words : &[Vec<u8>]) {
+    println!("Key text: {}", key_text(key));
+    let similarities = key_similarities(&Columns::new(inputs, key.len(), block), sample, metric);
+    let uncertain = low_confidence(&similarities[..]);
+    if !uncertain.is_empty() {
+        let positions : Vec<String> = uncertain.iter().map(|p| p.to_string()).collect();
+        println!("Low confidence positions: {}", positions.join(" "));
     }
-    else {
-        println!("No key found.");
+    if words.is_empty() {
+        return;
+    }
+    println!();
+    println!("Dictionary candidates:");
+    println!("----------------------\n");
+    println!("S        | K");
+    for c in complete_key(&similarities[..], words, 10).iter() {
+        println!("{:.6} : {}", c.0, key_text(&c.1[..]));
     }
 }
 
-fn find_length_candidates(data : &[u8], length : &mut Vec<Probabilistic<usize>>, max_l : usize) {
-    for l in range(1, max_l+1) {
-        length.push(Probabilistic{ p : 0f64, v : l});
-        for p in range(0, l) {
-            let mut freq = [0u64; 256];
-            let mut sum = 0u64;
-            let mut var = 0f64;
-            let mut i = p;
-            while i < data.len() {
-                freq[data[i] as usize] += 1u64;
-                sum += 1u64;
-                i += l;
-            }
-            for i in (0us..256) {
-                let diff = (freq[i] as f64 / sum as f64)-(1f64/256f64);
-                var += diff*diff;
-            }
-            length[l-1].p += var.sqrt() / (l as f64).powf(1.1);
-        }
-    }
-    length.sort_by(|a, b| {
-        if b.p < a.p { Ordering::Less }
-        else if b.p > a.p { Ordering::Greater }
-        else { Ordering::Equal }
-    });
+// Maximum key length, at least 1.
+fn parse_max_length(s : &str) -> Option<usize> {
+    s.parse().ok().filter(|k| *k > 0)
 }
 
-fn read_sample(path : &str, sample : &mut Sample)
-{
-    sample.data = match File::open(&Path::new(&path[])).read_to_end() {
-        Ok(d) => { d },
-        Err(e) => {
-            println!("Could not read sample file: {}", e);
-            panic!();
+// Sample made of the sample[:weight] specifications, exiting with the usage
+// when a weight is invalid and with an error when a sample can't be read.
+fn load_sample(specs : &[String]) -> Sample {
+    let mut sample_specs : Vec<(String, f64)> = Vec::new();
+    for spec in specs.iter() {
+        match parse_sample_spec(&spec[..]) {
+            Some(s) => sample_specs.push(s),
+            None => {
+                eprintln!("{} is not a valid sample weight", spec);
+                print_usage();
+                process::exit(1);
+            }
         }
-    };
-    let mut freq = [0u64; 256];
-    let mut sum = 0u64;
-    for c in sample.data.iter() {
-        sum += 1;
-        freq[*c as usize] += 1;
     }
-    for i in (0us..256) {
-        sample.unigram[i] = freq[i] as f64 / sum as f64;
+    let mut sample = Sample::new();
+    if let Err(e) = read_sample(&sample_specs[..], &mut sample) {
+        eprintln!("{}", e);
+        process::exit(1);
     }
+    sample
 }
 
-fn compute_unigram_var(u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
-    let mut cost : f64 = 0f64;
-    for i in (0us..256) {
-        let c = u1[i] - u2[s[i]];
-        cost += c*c;
+// Models of a -m value, every model for auto, exiting with the usage when the
+// model is invalid and with the reason when it can't be broken.
+fn load_models(spec : &str) -> Vec<Box<dyn CipherModel>> {
+    if spec == "auto" {
+        return auto_models();
+    }
+    let model = match parse_model(spec) {
+        Some(m) => m,
+        None => {
+            eprintln!("{} is not a valid model", spec);
+            print_usage();
+            process::exit(1);
+        }
+    };
+    if let Err(e) = model.check() {
+        eprintln!("{}", e);
+        process::exit(1);
     }
-    return cost;
+    vec![model]
 }
 
-fn compute_hamming_weight(a : u8) -> u8 {
-    (a & 1u8) + ((a & 2u8) >> 1) + ((a & 4u8) >> 2) + ((a & 8u8) >> 3) +
-    ((a & 16u8) >> 4) + ((a & 32u8) >> 5) + ((a & 64u8) >> 6) + ((a & 128u8) >> 7)
+// Comma separated list of positive numbers.
+fn parse_list(s : &str) -> Option<Vec<usize>> {
+    let mut list : Vec<usize> = Vec::new();
+    for e in s.split(',') {
+        match e.parse().ok() {
+            Some(v) if v > 0 => list.push(v),
+            _ => return None
+        }
+    }
+    Some(list)
 }
 
-fn compute_hamming_var(u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
-    let mut cost : f64 = 0f64;
-    let mut p1 : Vec<Probabilistic<u8>> = (0..256).map(|_| Probabilistic{p : 0f64, v : 0u8}).collect();
-    let mut p2 : Vec<Probabilistic<u8>> = (0..256).map(|_| Probabilistic{p : 0f64, v : 0u8}).collect();
-   for i in (0us..256) {
-        p1[i].v = compute_hamming_weight(s[i] as u8);
-        p1[i].p = u1[i];
-        p2[i].v = compute_hamming_weight(i as u8);
-        p2[i].p = u2[i];
-    }
-    p1.sort_by( |a, b| {
-        if a.v < b.v { Ordering::Less }
-        else if a.v > b.v { Ordering::Greater }
-        else {
-            if a.p < b.p { Ordering::Less }
-            else if a.p > b.p { Ordering::Greater }
-            else { Ordering::Equal }
+fn bench(args : &[String]) {
+    let mut config = BenchConfig::new();
+    let mut min_rate = 0.9f64;
+    let mut positional : Vec<String> = Vec::new();
+    let mut i = 0usize;
+    while i < args.len() {
+        if &args[i][..] == "-m" || &args[i][..] == "-l" || &args[i][..] == "-s" ||
+           &args[i][..] == "-n" || &args[i][..] == "--min-rate" || &args[i][..] == "--metric" {
+            if i + 1 >= args.len() {
+                eprintln!("No value given for {}", args[i]);
+                print_usage();
+                return;
+            }
+            let ok = match &args[i][..] {
+                "-m" => {
+                    let models : Vec<Option<Box<dyn CipherModel>>> = args[i+1].split(',').map(|m| parse_model(m)).collect();
+                    if models.iter().all(|m| m.as_ref().is_some_and(|m| m.check().is_ok())) {
+                        config.models = models.into_iter().map(|m| m.unwrap()).collect();
+                        true
+                    }
+                    else {
+                        false
+                    }
+                },
+                "-l" => parse_list(&args[i+1][..]).map(|l| config.lengths = l).is_some(),
+                "-s" => parse_list(&args[i+1][..]).map(|s| config.sizes = s).is_some(),
+                "-n" => args[i+1].parse().ok().filter(|n| *n > 0).map(|n| config.trials = n).is_some(),
+                "--min-rate" => args[i+1].parse().ok().map(|r| min_rate = r).is_some(),
+                _ => Metric::from_name(&args[i+1][..]).map(|m| config.metric = m).is_some(),
+            };
+            if !ok {
+                eprintln!("{} is not a valid value for {}", args[i+1], args[i]);
+                print_usage();
+                return;
+            }
+            i += 1;
         }
-    });
-    p2.sort_by( |a, b| {
-        if a.v < b.v { Ordering::Less }
-        else if a.v > b.v { Ordering::Greater }
         else {
-            if a.p < b.p { Ordering::Less }
-            else if a.p > b.p { Ordering::Greater }
-            else { Ordering::Equal }
+            positional.push(args[i].clone());
         }
-    });
-    for i in (0us..256) {
-        let c = p1[i].p - p2[i].p;
-        cost += c*c;
+        i += 1;
     }
-    return cost;
-}
 
-fn gen_lvl1_sub(x : u8, sub : &mut [usize; 256]) {
-    for i in (0us..256) {
-        sub[i] = i ^ x as usize;
+    if positional.is_empty() {
+        eprintln!("A corpus is required");
+        print_usage();
+        return;
     }
-}
+    let corpus = match fs::read(&positional[0]) {
+        Ok(d) => { d },
+        Err(e) => {eprintln!("Could not read corpus file: {}", e); return;}
+    };
+    if corpus.is_empty() {
+        eprintln!("The corpus is empty");
+        return;
+    }
+    let sample = if positional.len() > 1 {
+        load_sample(&positional[1..])
+    }
+    else {
+        Sample::from_data(corpus.clone())
+    };
 
-fn gen_lvl2_sub(x : u8, a : u8, sub : &mut [usize; 256]) {
-    for i in (0us..256) {
-        sub[i] = ((i as u8 ^ x) + a) as usize;
+    let results = run_bench(&corpus[..], &sample, &config);
+    println!("M           | l   | size     | recovered");
+    for r in results.iter() {
+        println!("{:11} : {:3} : {:8} : {}/{}", r.model.name(), r.length, r.size, r.recovered, r.trials);
+    }
+    println!();
+    let mut failed = false;
+    for m in config.models.iter() {
+        let rate = recovery_rate(&results[..], &**m);
+        println!("Recovery rate {}: {:.2}%", m.name(), rate * 100f64);
+        // A NaN rate means nothing was measured, which isn't a success.
+        if rate.is_nan() || rate < min_rate {
+            failed = true;
+        }
+    }
+    if failed {
+        eprintln!("Recovery rate is lower than {:.2}%", min_rate * 100f64);
+        process::exit(1);
     }
 }
 
-fn gen_lvl3_sub(x : u8, a : u8, m : u16, sub : &mut [usize; 256]) {
-    let c = [40320u16, 5040u16, 720u16, 120u16, 24u16, 6u16, 2u16, 1u16, 1u16];
-    let mut used = [false; 8];
-    let mut p = [0us; 8];
-    for i in (0us..8) {
-        p[i] = ((m%c[i])/c[i+1]+1) as usize;
-        for j in (0us..8) {
-            if used[j] == false {
-                p[i] -= 1;
-            }
-            if p[i] == 0 {
-                p[i] = j;
-                used[j] = true;
-                break;
-            }
-        }
-    }
-    for i in (0us..256) {
-        let b = (i as u8 ^ x) + a;
-        sub[i] = ((b & 1u8) << p[0] |
-                 ((b & 2u8) >> 1) << p[1] |
-                 ((b & 4u8) >> 2) << p[2] |
-                 ((b & 8u8) >> 3) << p[3] |
-                 ((b & 16u8) >> 4) << p[4] |
-                 ((b & 32u8) >> 5) << p[5] |
-                 ((b & 64u8) >> 6) << p[6] |
-                 ((b & 128u8) >> 7) << p[7]) as usize;
+fn segment(args : &[String]) {
+    let mut models : Vec<Box<dyn CipherModel>> = vec![Box::new(XorModel) as Box<dyn CipherModel>];
+    let mut max_length = 10usize;
+    let mut window = DEFAULT_SEGMENT_WINDOW;
+    let mut metric = Metric::L2;
+    let mut output : Option<String> = None;
+    let mut positional : Vec<String> = Vec::new();
+    let mut i = 0usize;
+    while i < args.len() {
+        if &args[i][..] == "-m" || &args[i][..] == "-k" || &args[i][..] == "-w" ||
+           &args[i][..] == "--metric" || &args[i][..] == "-o" {
+            if i + 1 >= args.len() {
+                eprintln!("No value given for {}", args[i]);
+                print_usage();
+                return;
+            }
+            let ok = match &args[i][..] {
+                "-m" => { models = load_models(&args[i+1][..]); true },
+                "-k" => parse_max_length(&args[i+1][..]).map(|k| max_length = k).is_some(),
+                "-w" => match args[i+1].parse().ok() {
+                    Some(w) if w >= 256 => { window = w; true },
+                    _ => false
+                },
+                "-o" => { output = Some(args[i+1].clone()); true },
+                _ => Metric::from_name(&args[i+1][..]).map(|m| metric = m).is_some(),
+            };
+            if !ok {
+                eprintln!("{} is not a valid value for {}", args[i+1], args[i]);
+                print_usage();
+                return;
+            }
+            i += 1;
+        }
+        else {
+            positional.push(args[i].clone());
+        }
+        i += 1;
+    }
+
+    if positional.len() < 2 {
+        eprintln!("An input and at least one sample are required");
+        print_usage();
+        return;
+    }
+    let sample = load_sample(&positional[1..]);
+    let data = match fs::read(&positional[0]) {
+        Ok(d) => { d },
+        Err(e) => {eprintln!("Could not read input file: {}", e); return;}
+    };
+
+    let segments = find_segments(&models[..], &data[..], &sample, &metric, max_length, window);
+    println!("start    | end      | S        | l   | K");
+    let mut plain : Vec<u8> = Vec::with_capacity(data.len());
+    for s in segments.iter() {
+        let d = &data[s.start..s.end];
+        match s.candidate {
+            Some(ref c) => {
+                println!("{:8} : {:8} : {:.6} : {:3} : {} ({})", s.start, s.end, c.score, c.length,
+                         c.model.format_key(&c.key), c.model.name());
+                plain.extend_from_slice(&decipher(&*c.model, &c.key, d, 0)[..]);
+            },
+            None => {
+                println!("{:8} : {:8} : -        :   - : No key found.", s.start, s.end);
+                plain.extend_from_slice(d);
+            }
+        }
+    }
+    if let Some(ref path) = output {
+        if let Err(e) = fs::write(path, &plain) {
+            eprintln!("Could not write output file: {}", e);
+        }
     }
 }
 
-fn break_lvl1(data : &[u8], sample : &Sample, l : usize, key : &mut Vec<Vec<u8>>) -> f64 {
-    let mut unigram : Vec<[f64; 256]> = (0..l).map(|_| [0f64; 256]).collect();
-    let mut score : Vec<f64> = repeat(1f64).take(l).collect();
-    key.clear();
-    key.push(repeat(0u8).take(l).collect());
-    for p in (0..l) {
-        let mut i = p;
-        let mut freq = [0u64; 256];
-        let mut sub = [0us; 256];
-        let mut sum = 0u64;
-        while i < data.len() {
-            sum += 1;
-            freq[data[i] as usize] += 1; 
-            i += l;
-        }
-        for i in (0us..256) {
-            unigram[p][i] = freq[i] as f64 / sum as f64;
-        }
-        for k in (0us..256) {
-            gen_lvl1_sub(k as u8, &mut sub);
-            let s = compute_unigram_var(&sample.unigram, &unigram[p], &sub);
-            if s < score[p] {
-                score[p] = s;
-                key[0][p] = k as u8;
-            }
-        }
-    }
-    return score.iter().fold(1f64, |a, &v| a - v.sqrt() / l as f64);
+fn cluster(args : &[String]) {
+    let mut models : Vec<Box<dyn CipherModel>> = vec![Box::new(XorModel) as Box<dyn CipherModel>];
+    let mut max_length = 10usize;
+    let mut block = 0usize;
+    let mut metric = Metric::L2;
+    let mut positional : Vec<String> = Vec::new();
+    let mut i = 0usize;
+    while i < args.len() {
+        if &args[i][..] == "-m" || &args[i][..] == "-k" || &args[i][..] == "--block-size" ||
+           &args[i][..] == "--metric" {
+            if i + 1 >= args.len() {
+                eprintln!("No value given for {}", args[i]);
+                print_usage();
+                return;
+            }
+            let ok = match &args[i][..] {
+                "-m" => { models = load_models(&args[i+1][..]); true },
+                "-k" => parse_max_length(&args[i+1][..]).map(|k| max_length = k).is_some(),
+                "--block-size" => args[i+1].parse().ok().map(|b| block = b).is_some(),
+                _ => Metric::from_name(&args[i+1][..]).map(|m| metric = m).is_some(),
+            };
+            if !ok {
+                eprintln!("{} is not a valid value for {}", args[i+1], args[i]);
+                print_usage();
+                return;
+            }
+            i += 1;
+        }
+        else {
+            positional.push(args[i].clone());
+        }
+        i += 1;
+    }
+
+    if positional.len() < 2 {
+        eprintln!("A directory and at least one sample are required");
+        print_usage();
+        return;
+    }
+    let sample = load_sample(&positional[1..]);
+    let files = match expand_inputs(&positional[..1]) {
+        Ok(f) => f,
+        Err(e) => {eprintln!("{}", e); return;}
+    };
+
+    let results = crack_files(&models[..], &files[..], &sample, &metric, max_length, block, num_cpus(), None);
+    for r in results.iter() {
+        if let Some(ref e) = r.error {
+            eprintln!("{}: {}", r.path.display(), e);
+        }
+    }
+    let candidates : Vec<Option<Candidate>> = results.into_iter().map(|r| r.candidate).collect();
+    let clusters = cluster_keys(&candidates[..]);
+
+    println!("Files:");
+    println!("------\n");
+    println!("C   | phase | S        | file");
+    for (c, k) in clusters.iter().enumerate() {
+        for m in k.members.iter() {
+            println!("{:3} : {:5} : {:.6} : {}", c + 1, m.phase, m.score, files[m.file].display());
+        }
+    }
+    for (f, candidate) in files.iter().zip(candidates.iter()) {
+        if candidate.is_none() {
+            println!("  - :     - : -        : {}", f.display());
+        }
+    }
+    print!("\n\n");
+    println!("Clusters:");
+    println!("---------\n");
+    println!("C   | n   | S        | K");
+    for (c, k) in clusters.iter().enumerate() {
+        println!("{:3} : {:3} : {:.6} : {} ({})", c + 1, k.members.len(), k.mean_score(),
+                 k.model.format_key(&k.key), k.model.name());
+    }
 }
 
-fn break_lvl2(data : &[u8], sample : &Sample, l : usize, key : &mut Vec<Vec<u8>>) -> f64 {
-    let mut unigram : Vec<[f64; 256]> = (0..l).map(|_| [0f64; 256]).collect();
-    let mut score : Vec<f64> = repeat(1f64).take(l).collect();
-    key.clear();
-    key.push(repeat(0u8).take(l).collect());
-    key.push(repeat(0u8).take(l).collect());
-    for p in (0..l) {
-        let mut i = p;
-        let mut freq = [0u64; 256];
-        let mut sum = 0u64;
-        while i < data.len() {
-            sum += 1;
-            freq[data[i] as usize] += 1; 
-            i += l;
-        }
-        for i in (0us..256) {
-            unigram[p][i] = freq[i] as f64 / sum as f64;
-        }
-    }
-    let (tx, rx) = channel::<SBTask>();
-    for p in (0..l) {
-        let tx = tx.clone();
-        let (u_tx, u_rx) = channel::<[f64; 256]>();
-        u_tx.send(sample.unigram);
-        u_tx.send(unigram[p]);
-        Thread::spawn(move || {
-            let mut sub = [0us; 256];
-            let mut res = SBTask {x : 0u8, a : 0u8, m : 0u16, p : p, score : 1f64};
-            let du = u_rx.recv().unwrap();
-            let u = u_rx.recv().unwrap();
-            for x in (0..256) {
-                for a in (0..256) {
-                    gen_lvl2_sub(x as u8, a as u8, &mut sub);
-                    let s = compute_unigram_var(&du, &u, &sub);
-                    if s < res.score {
-                        res.score = s;
-                        res.x = x as u8;
-                        res.a = a as u8;
-                    }
-                }
+fn analyze(args : &[String]) {
+    let mut window = DEFAULT_WINDOW;
+    let mut max_length = 10usize;
+    let mut metric = Metric::L2;
+    let mut verbose = false;
+    let mut positional : Vec<String> = Vec::new();
+    let mut i = 0usize;
+    while i < args.len() {
+        if &args[i][..] == "-w" || &args[i][..] == "-k" || &args[i][..] == "--metric" {
+            if i + 1 >= args.len() {
+                eprintln!("No value given for {}", args[i]);
+                print_usage();
+                return;
+            }
+            let ok = match &args[i][..] {
+                "-w" => match args[i+1].parse().ok() {
+                    Some(w) if w >= 256 => { window = w; true },
+                    _ => false
+                },
+                "-k" => parse_max_length(&args[i+1][..]).map(|k| max_length = k).is_some(),
+                _ => Metric::from_name(&args[i+1][..]).map(|m| metric = m).is_some(),
+            };
+            if !ok {
+                eprintln!("{} is not a valid value for {}", args[i+1], args[i]);
+                print_usage();
+                return;
             }
-            tx.send(res);
-        });
+            i += 1;
+        }
+        else if &args[i][..] == "-v" {
+            verbose = true;
+        }
+        else {
+            positional.push(args[i].clone());
+        }
+        i += 1;
+    }
+
+    if positional.is_empty() {
+        eprintln!("An input is required");
+        print_usage();
+        return;
+    }
+    let data = match fs::read(&positional[0]) {
+        Ok(d) => { d },
+        Err(e) => {eprintln!("Could not read input file: {}", e); return;}
+    };
+    if data.is_empty() {
+        eprintln!("The input is empty");
+        return;
+    }
+    let sample = if positional.len() > 1 { Some(load_sample(&positional[1..])) } else { None };
+    let sample = sample.as_ref();
+
+    let windows = entropy_map(&data[..], window, max_length, sample, &metric);
+    if verbose {
+        println!("Entropy map:");
+        println!("------------\n");
+        println!("offset   | H    | l   | P    | ");
+        for w in windows.iter() {
+            let bar : String = (0..(w.entropy * 4f64).round() as usize).map(|_| '#').collect();
+            println!("{:8} : {:.2} : {:3} : {:.2} : {:32} {}", w.offset, w.entropy, w.period, w.periodicity,
+                     bar, w.class.describe());
+        }
+        print!("\n\n");
     }
-    for p in (0..l) {
-        let res = rx.recv().unwrap();
-        score[res.p] = res.score;
-        key[0][res.p] = res.x as u8;
-        key[1][res.p] = res.a as u8;
+    println!("Regions:");
+    println!("--------\n");
+    println!("start    | end      | H    | class");
+    for r in regions(&windows[..]).iter() {
+        println!("{:8} : {:8} : {:.2} : {}", r.start, r.end, r.entropy, r.class.describe());
     }
-    return score.iter().fold(1f64, |a, &v| a - v.sqrt() / l as f64);
 }
 
-fn break_lvl3(data : &[u8], sample : &Sample, l : usize, key : &mut Vec<Vec<u8>>) -> f64 {
-    let mut unigram : Vec<[f64; 256]> = (0..l).map(|_| [0f64; 256]).collect();
-    let mut score : Vec<f64> = repeat(1f64).take(l).collect();
-    key.clear();
-    key.push(repeat(0u8).take(l).collect());
-    key.push(repeat(0u8).take(l).collect());
-    key.push(repeat(0u8).take(2*l).collect());
-    for p in (0..l) {
-        let mut i = p;
-        let mut freq = [0u64; 256];
-        let mut sum = 0u64;
-        while i < data.len() {
-            sum += 1;
-            freq[data[i] as usize] += 1; 
-            i += l;
-        }
-        for i in (0us..256) {
-            unigram[p][i] = freq[i] as f64 / sum as f64;
-        }
-    }
-    let (tx, rx) = channel::<SBTask>();
-    for p in (0..l) {
-        let tx = tx.clone();
-        let (u_tx, u_rx) = channel::<[f64; 256]>();
-        u_tx.send(sample.unigram);
-        u_tx.send(unigram[p]);
-        Thread::spawn(move || {
-            let mut sub = [0us; 256];
-            let mut res = SBTask {x : 0u8, a : 0u8, m : 0u16, p : p,score : 1f64};
-            let mut candidates : Vec<Probabilistic<[u8; 2]>> = Vec::new();
-            let du = u_rx.recv().unwrap();
-            let u = u_rx.recv().unwrap();
-            for x in (0..256) {
-                for a in (0..256) {
-                    gen_lvl2_sub(x as u8, a as u8, &mut sub);
-                    let s = compute_hamming_var(&du, &u, &sub);
-                    candidates.push(Probabilistic{p : s, v : [x as u8, a as u8]});
-                }
+fn batch(args : &[String]) {
+    let mut models : Vec<Box<dyn CipherModel>> = vec![Box::new(XorModel) as Box<dyn CipherModel>];
+    let mut max_length = 10usize;
+    let mut block = 0usize;
+    let mut metric = Metric::L2;
+    let mut jobs = num_cpus();
+    let mut sample_specs : Vec<String> = Vec::new();
+    let mut output : Option<String> = None;
+    let mut inputs : Vec<String> = Vec::new();
+    let mut i = 0usize;
+    while i < args.len() {
+        if &args[i][..] == "-m" || &args[i][..] == "-k" || &args[i][..] == "--block-size" ||
+           &args[i][..] == "--metric" || &args[i][..] == "-j" || &args[i][..] == "-f" ||
+           &args[i][..] == "-s" || &args[i][..] == "-o" {
+            if i + 1 >= args.len() {
+                eprintln!("No value given for {}", args[i]);
+                print_usage();
+                return;
             }
-            candidates.sort_by(|a, b| {
-                if a.p < b.p { Ordering::Less }
-                else if a.p > b.p { Ordering::Greater }
-                else { Ordering::Equal }
-            });
-            res.score = 1f64;
-            for i in (0us..40) {
-                let ref c = candidates[i];
-                if c.p > res.score || c.p > 0.01{
-                    break;
-                }
-                for m in (0u16..40320) {
-                    gen_lvl3_sub(c.v[0], c.v[1], m, &mut sub);
-                    let s = compute_unigram_var(&du, &u, &sub);
-                    if s < res.score {
-                        res.score = s;
-                        res.x = c.v[0];
-                        res.a = c.v[1];
-                        res.m = m;
+            let ok = match &args[i][..] {
+                "-m" => { models = load_models(&args[i+1][..]); true },
+                "-k" => parse_max_length(&args[i+1][..]).map(|k| max_length = k).is_some(),
+                "--block-size" => args[i+1].parse().ok().map(|b| block = b).is_some(),
+                "-j" => match args[i+1].parse().ok() {
+                    Some(j) if j > 0 => { jobs = j; true },
+                    _ => false
+                },
+                "-f" => match read_file_list(&args[i+1][..]) {
+                    Ok(list) => { inputs.extend(list); true },
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
                     }
-                }
+                },
+                "-s" => {
+                    sample_specs.push(args[i+1].clone());
+                    true
+                },
+                "-o" => { output = Some(args[i+1].clone()); true },
+                _ => Metric::from_name(&args[i+1][..]).map(|m| metric = m).is_some(),
+            };
+            if !ok {
+                eprintln!("{} is not a valid value for {}", args[i+1], args[i]);
+                print_usage();
+                return;
             }
-            tx.send(res);
-        });
+            i += 1;
+        }
+        else {
+            inputs.push(args[i].clone());
+        }
+        i += 1;
+    }
+
+    if inputs.is_empty() || sample_specs.is_empty() {
+        eprintln!("At least one input and one sample are required");
+        print_usage();
+        return;
     }
-    let mut aborted = false;
-    for p in (0..l) {
-        let res = rx.recv().unwrap();
-        if res.score == 1f64 {
-            aborted = true;
+    let files = match expand_inputs(&inputs[..]) {
+        Ok(f) => f,
+        Err(e) => {eprintln!("{}", e); return;}
+    };
+    let sample = load_sample(&sample_specs[..]);
+    let outputs = match output {
+        Some(ref dir) => {
+            let dir = Path::new(dir);
+            if let Err(e) = fs::create_dir_all(dir) {
+                eprintln!("Could not create output directory: {}", e);
+                return;
+            }
+            Some(output_paths(&files[..], dir))
+        },
+        None => None
+    };
+
+    let results = crack_files(&models[..], &files[..], &sample, &metric, max_length, block, jobs,
+                              outputs.as_ref().map(|o| &o[..]));
+    print_batch_results(&results[..]);
+}
+
+fn print_batch_results(results : &[FileResult]) {
+    println!("S        | l   | size     | file | type | K");
+    let mut broken = 0usize;
+    for r in results.iter() {
+        match r.candidate {
+            Some(ref c) => {
+                broken += 1;
+                print!("{:.6} : {:3} : {:8} : {} : {} : {}", c.score, c.length, r.size, r.path.display(),
+                       r.file_type.as_ref().map_or("-", |t| &t[..]), c.model.format_key(&c.key));
+                print!(" ({})", c.model.name());
+            },
+            None => print!("-        :   - : {:8} : {} : - : No key found.", r.size, r.path.display())
         }
-        score[res.p] = res.score;
-        key[0][res.p] = res.x as u8;
-        key[1][res.p] = res.a as u8;
-        key[2][2*res.p] = (res.m >> 8) as u8;
-        key[2][2*res.p+1] = (res.m & 0xff) as u8;
+        if let Some(ref e) = r.error {
+            print!(" {}", e);
+        }
+        println!();
     }
-    if aborted {
-        return 0f64;
+    println!();
+    println!("Broken: {}/{} files", broken, results.len());
+}
+
+// Inclusive range of seeds written from-to, returned with an exclusive end.
+fn parse_range(s : &str) -> Option<(u64, u64)> {
+    let i = s.find('-')?;
+    match (s[..i].parse::<u64>().ok(), s[i+1..].parse::<u64>().ok()) {
+        (Some(from), Some(to)) if from <= to && to < u64::MAX => Some((from, to + 1)),
+        _ => None
     }
-    return score.iter().fold(1f64, |a, &v| a - v.sqrt() / l as f64);
 }
 
-fn break_lvl4(data : &[u8], sample : &Sample, l : usize, key : &mut Vec<Vec<u8>>) -> f64 {
-    let mut unigram : Vec<Vec<Probabilistic<u8>>> = (0..l).map(|_| (0..l).map(|_| Probabilistic {p : 0f64, v : 0u8}).collect()).collect();
-    let mut su : Vec<Probabilistic<u8>> = (0..256).map(|_| Probabilistic {p : 0f64, v : 0u8}).collect();
-    key.clear();
-    for i in (0..l) {
-        key.push((0..256).map(|i| i as u8).collect());
-    }
-    for i in (0us..256) {
-        su[i].v = i as u8;
-        su[i].p = sample.unigram[i];
-    }
-    su.sort_by( |a, b| {
-        if b.p < a.p { Ordering::Less }
-        else if b.p > a.p { Ordering::Greater }
-        else { Ordering::Equal }
-    });
-    for p in (0..l) {
-        let mut i = p;
-        let mut freq = [0u64; 256];
-        let mut sum = 0u64;
-        while i < data.len() {
-            sum += 1;
-            freq[data[i] as usize] += 1; 
-            i += l;
-        }
-        for i in (0us..256) {
-            unigram[p][i].v = i as u8;
-            unigram[p][i].p = freq[i] as f64 / sum as f64;
-        }
-        unigram[p].sort_by( |a, b| {
-            if b.p < a.p { Ordering::Less }
-            else if b.p > a.p { Ordering::Greater }
-            else { Ordering::Equal }
-        });
-        for i in (0us..256) {
-            key[p][su[i].v as usize] = unigram[p][i].v;
-        }
-    }
-    return 0f64;
+// Output shift of a byte generator, written byte or byte:shift.
+fn parse_byte_shift(s : &str) -> Option<usize> {
+    if s == "byte" {
+        return Some(0);
+    }
+    match s.strip_prefix("byte:").and_then(|n| n.parse().ok()) {
+        Some(shift) if shift <= MAX_BYTE_SHIFT => Some(shift),
+        _ => None
+    }
 }
 
-fn print_key(key : &Vec<Vec<u8>>) {
-    print!("x = ");
-    for b in key[0].iter() {
-        print!("{:02x}", *b);
+fn lcg(args : &[String]) {
+    let mut generators : Option<Vec<Lcg>> = None;
+    let mut byte : Option<usize> = None;
+    let mut known : Option<(usize, Vec<u8>)> = None;
+    let mut seeds : Option<(u64, u64)> = None;
+    let mut metric = Metric::L2;
+    let mut output : Option<String> = None;
+    let mut positional : Vec<String> = Vec::new();
+    let mut i = 0usize;
+    while i < args.len() {
+        if &args[i][..] == "-g" || &args[i][..] == "-p" || &args[i][..] == "--seeds" ||
+           &args[i][..] == "--metric" || &args[i][..] == "-o" {
+            if i + 1 >= args.len() {
+                eprintln!("No value given for {}", args[i]);
+                print_usage();
+                return;
+            }
+            let ok = match &args[i][..] {
+                "-g" => {
+                    byte = parse_byte_shift(&args[i+1][..]);
+                    byte.is_some() || Lcg::from_name(&args[i+1][..]).map(|g| generators = Some(vec![g])).is_some()
+                },
+                "-p" => parse_known(&args[i+1][..]).map(|k| known = Some(k)).is_some(),
+                "--seeds" => parse_range(&args[i+1][..]).map(|r| seeds = Some(r)).is_some(),
+                "-o" => {
+                    output = Some(args[i+1].clone());
+                    true
+                },
+                _ => Metric::from_name(&args[i+1][..]).map(|m| metric = m).is_some(),
+            };
+            if !ok {
+                eprintln!("{} is not a valid value for {}", args[i+1], args[i]);
+                print_usage();
+                return;
+            }
+            i += 1;
+        }
+        else {
+            positional.push(args[i].clone());
+        }
+        i += 1;
+    }
+
+    if positional.is_empty() || (known.is_none() && positional.len() < 2) {
+        eprintln!("An input and, without known plaintext, at least one sample are required");
+        print_usage();
+        return;
+    }
+    if byte.is_some() && known.is_none() {
+        eprintln!("The byte generator parameters can only be recovered with a known plaintext");
+        return;
     }
-    if key.len() > 1 {
-        print!(" a = ");
-        for b in key[1].iter() {
-            print!("{:02x}", *b);
+    let data = match fs::read(&positional[0]) {
+        Ok(d) => { d },
+        Err(e) => {eprintln!("Could not read input file: {}", e); return;}
+    };
+    if data.is_empty() {
+        eprintln!("The input is empty");
+        return;
+    }
+    if let Some((offset, ref k)) = known {
+        if offset + k.len() > data.len() {
+            eprintln!("The known plaintext goes past the end of the input");
+            return;
+        }
+    }
+    let sample = if positional.len() > 1 { load_sample(&positional[1..]) } else { Sample::new() };
+
+    let mut matches : Vec<SeedMatch> = Vec::new();
+    if let Some(shift) = byte {
+        let (offset, ref k) = known.clone().unwrap();
+        if k.len() < 3 {
+            eprintln!("The byte generator parameters need at least 3 known bytes");
+            return;
+        }
+        matches = find_byte_lcgs(&data[..], offset, &k[..], shift, 10);
+    }
+    else {
+        for g in generators.unwrap_or_else(Lcg::known).iter() {
+            let (from, to) = match seeds {
+                Some((from, to)) => (from, if to > g.seeds() { g.seeds() } else { to }),
+                None if known.is_some() => (0, g.seeds()),
+                None => (0, if g.seeds() < DEFAULT_STAT_SEEDS { g.seeds() } else { DEFAULT_STAT_SEEDS })
+            };
+            if from >= to {
+                continue;
+            }
+            match known {
+                Some((offset, ref k)) => matches.extend(find_seeds_known(g, &data[..], offset, &k[..], from, to, 10)),
+                None => matches.push(find_seed_stat(g, &data[..], &sample, &metric, from, to))
+            }
+        }
+    }
+    // Several seeds can match a short known plaintext, rank them with the
+    // samples when there are some.
+    if positional.len() > 1 {
+        for m in matches.iter_mut() {
+            m.score = metric.score_plaintext(&sample, &subbuster::lcg::decipher(&m.lcg, m.seed, &data[..])[..]);
         }
     }
-    if key.len() > 2 {
-        print!(" m = ");
-        for b in key[2].iter() {
-            print!("{:02x}", *b);
+    // NaN scores, from a degenerate sample, are ranked last.
+    matches.sort_by(|a, b| match (a.score.is_nan(), b.score.is_nan()) {
+        (false, false) => b.score.partial_cmp(&a.score).unwrap(),
+        (x, y) => x.cmp(&y)
+    });
+
+    if matches.is_empty() {
+        println!("No seed found.");
+        return;
+    }
+    println!("S        | G                    | seed");
+    for m in matches.iter() {
+        println!("{:.6} : {:20} : {:08x}", m.score, m.lcg.name, m.seed);
+    }
+    let best = &matches[0];
+    println!("\nBest seed: {:.6} : {} : {:08x}", best.score, best.lcg.name, best.seed);
+    if let Some(ref path) = output {
+        let plain = subbuster::lcg::decipher(&best.lcg, best.seed, &data[..]);
+        if let Err(e) = fs::write(path, &plain) {
+            eprintln!("Could not write output file: {}", e);
         }
     }
 }
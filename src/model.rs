@@ -0,0 +1,1027 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::Ordering;
+use crate::rng::Rng;
+use std::cmp::min;
+use subcipher::{apply_subs, format_mix_key, gen_sub, gen_xor_add_sub, gen_xor_sub, inv_sub, lcm, period, MIX_COUNT};
+use subcipher::pipeline::{Op, Pipeline, Step};
+use subcipher::word::{Word, WordOp};
+
+use super::{Columns, Probabilistic, Sample, identity_sub, mean_similarity, search_columns};
+use crate::constraint::Constraints;
+use crate::scorer::{Scorer, compute_hamming_var};
+
+// A family of keyed byte substitutions the breaker can search. A key is a list
+// of components, each one holding the bytes of every key position.
+pub trait CipherModel : Send + Sync {
+    fn name(&self) -> String;
+
+    // Number of key bits per key byte, used to penalise complex models in
+    // automatic model selection.
+    fn key_bits(&self) -> f64;
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize;
+
+    // Check that the key space of the model can be searched.
+    fn check(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Number of positions the key length has to be a multiple of, other
+    // lengths being extended to their least common multiple.
+    fn key_period(&self) -> usize {
+        1
+    }
+
+    // Number of positions after which the substitution tables of the key
+    // repeat.
+    fn subs_period(&self, key : &[Vec<u8>]) -> usize {
+        self.key_length(key)
+    }
+
+    // Substitution table ciphering each key position.
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]>;
+
+    // Substitution table deciphering each key position.
+    fn gen_key_inv_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        let mut subs = self.gen_key_subs(key);
+        for s in subs.iter_mut() {
+            inv_sub(s);
+        }
+        subs
+    }
+
+    // Whether each key position is a byte substitution, only then can keys be
+    // reduced to a simpler model.
+    fn is_substitution(&self) -> bool {
+        true
+    }
+
+    fn encipher(&self, key : &[Vec<u8>], data : &[u8]) -> Vec<u8> {
+        let mut cipher = data.to_vec();
+        apply_subs(&mut cipher[..], &self.gen_key_subs(key)[..]);
+        cipher
+    }
+
+    fn decipher(&self, key : &[Vec<u8>], data : &[u8]) -> Vec<u8> {
+        let mut plain = data.to_vec();
+        apply_subs(&mut plain[..], &self.gen_key_inv_subs(key)[..]);
+        plain
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String;
+
+    // Key made of the first period positions of the key. When the components
+    // of a key can have different periods, each one is reduced to its own.
+    fn truncate_key(&self, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>>;
+
+    // Key of this model producing the substitution tables, if any. Keys are
+    // reported in the simplest model able to produce them.
+    fn find_key(&self, _subs : &[[usize; 256]]) -> Option<Vec<Vec<u8>>> {
+        None
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>>;
+
+    // Search the key of each column of the input. Returns the score of the
+    // key, 0 if the search was aborted.
+    fn break_key(&self, columns : &Columns, sample : &Sample, scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64;
+
+    fn box_clone(&self) -> Box<dyn CipherModel>;
+}
+
+impl Clone for Box<dyn CipherModel> {
+    fn clone(&self) -> Box<dyn CipherModel> {
+        self.box_clone()
+    }
+}
+
+// Key length l extended to a multiple of the key period.
+pub fn extend_length(l : usize, period : usize) -> usize {
+    lcm(l, period)
+}
+
+// Columns of the extended key length when it differs, built from the segments
+// of the input so that the key still restarts at each of them.
+fn extend_columns(columns : &Columns, period : usize) -> Option<Columns> {
+    let l = extend_length(columns.length, period);
+    if l == columns.length {
+        return None;
+    }
+    let segments : Vec<&[u8]> = columns.segments.iter().map(|s| &s[..]).collect();
+    Some(Columns::new(&segments[..], l, 0))
+}
+
+// Model by level number, name or sequence of operations. A single operation
+// followed by a step, like xor-inc, is a stream model and names like add32le
+// are word models.
+pub fn parse_model(s : &str) -> Option<Box<dyn CipherModel>> {
+    match s {
+        "1" => return Some(Box::new(XorModel) as Box<dyn CipherModel>),
+        "2" => return Some(Box::new(XorAddModel) as Box<dyn CipherModel>),
+        "3" => return Some(Box::new(XorAddMixModel) as Box<dyn CipherModel>),
+        "4" | "frequency" => return Some(Box::new(FrequencyModel) as Box<dyn CipherModel>),
+        _ => {}
+    }
+    if let Some(i) = s.rfind('-').or(s.rfind(',')) {
+        if let Some(step) = Step::from_name(&s[i+1..]) {
+            return match Pipeline::parse(&s[..i]) {
+                Ok(p) => if p.ops.len() == 1 && p.ops[0].key_space() == 256 {
+                    Some(Box::new(StreamModel {pipeline : p, step}) as Box<dyn CipherModel>)
+                } else {
+                    None
+                },
+                Err(_) => None
+            };
+        }
+    }
+    if let Some(w) = Word::parse(s) {
+        return Some(Box::new(WordModel {word : w}) as Box<dyn CipherModel>);
+    }
+    match Pipeline::parse(s) {
+        Ok(p) => Some(model_from_pipeline(p)),
+        Err(_) => None
+    }
+}
+
+// Model of a pipeline, the ones matching a level use its dedicated search.
+pub fn model_from_pipeline(pipeline : Pipeline) -> Box<dyn CipherModel> {
+    match &pipeline.name()[..] {
+        "xor" => Box::new(XorModel) as Box<dyn CipherModel>,
+        "xor-add" => Box::new(XorAddModel) as Box<dyn CipherModel>,
+        "xor-add-mix" => Box::new(XorAddMixModel) as Box<dyn CipherModel>,
+        _ => Box::new(PipelineModel {pipeline, constraints : Constraints::new()}) as Box<dyn CipherModel>
+    }
+}
+
+// Model searching only the keys allowed by fixed keys and ranges, see
+// Constraints::add. Levels are searched as their pipeline.
+pub fn constrained_model(s : &str, fixed : &[String], ranges : &[String]) -> Result<Box<dyn CipherModel>, String> {
+    let ops = match s {
+        "1" => "xor",
+        "2" => "xor,add",
+        "3" => "xor,add,mix",
+        _ => s
+    };
+    let pipeline = match Pipeline::parse(ops) {
+        Ok(p) => p,
+        Err(_) => return Err(format!("{} can't be constrained, only sequences of operations can", s))
+    };
+    let mut constraints = Constraints::new();
+    for f in fixed.iter() {
+        constraints.add(&pipeline, &f[..], false)?;
+    }
+    for r in ranges.iter() {
+        constraints.add(&pipeline, &r[..], true)?;
+    }
+    let model = PipelineModel {pipeline, constraints};
+    model.check()?;
+    Ok(Box::new(model) as Box<dyn CipherModel>)
+}
+
+// Models tried in increasing complexity by the automatic model selection.
+pub fn auto_models() -> Vec<Box<dyn CipherModel>> {
+    vec![Box::new(XorModel) as Box<dyn CipherModel>,
+         parse_model("nxor").unwrap(),
+         Box::new(XorAddModel) as Box<dyn CipherModel>,
+         Box::new(XorAddMixModel) as Box<dyn CipherModel>]
+}
+
+// Models keys are reduced to when possible, simplest first.
+pub fn reduced_models() -> Vec<Box<dyn CipherModel>> {
+    vec![Box::new(XorModel) as Box<dyn CipherModel>,
+         Box::new(XorAddModel) as Box<dyn CipherModel>]
+}
+
+// Pipeline of a level, used for the keys whose components have different
+// periods.
+fn level_pipeline(ops : &str) -> Pipeline {
+    Pipeline::parse(ops).unwrap()
+}
+
+// Xoring and adding 0x80 are the same, so flipping the top bits of both x and
+// a gives the same tables. The top bits of x are moved to a before the key is
+// compacted, so that equivalent keys are all written the same way, with the
+// smallest x unless the components need the top bits to be periodic.
+fn compact_xor_add_key(ops : &str, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>> {
+    let p = level_pipeline(ops);
+    let mut t = p.truncate_key(key, period);
+    let (x, rest) = t.split_at_mut(1);
+    for (x, a) in x[0].iter_mut().zip(rest[0].iter_mut()) {
+        *a ^= *x & 0x80;
+        *x &= 0x7f;
+    }
+    p.compact_key(&t)
+}
+
+fn format_hex(k : &[u8]) -> String {
+    k.iter().map(|b| format!("{:02x}", *b)).collect()
+}
+
+fn find_lvl1_key(s : &[usize; 256]) -> Option<u8> {
+    let mut sub = [0usize; 256];
+    // sub[0] = 0 ^ x
+    let x = s[0] as u8;
+    gen_xor_sub(x, &mut sub);
+    if sub[..] == s[..] { Some(x) } else { None }
+}
+
+fn find_lvl2_key(s : &[usize; 256]) -> Option<(u8, u8)> {
+    let mut sub = [0usize; 256];
+    for x in 0usize..256 {
+        // sub[0] = (0 ^ x) + a
+        let a = (s[0] as u8).wrapping_sub(x as u8);
+        gen_xor_add_sub(x as u8, a, &mut sub);
+        if sub[..] == s[..] {
+            return Some((x as u8, a));
+        }
+    }
+    None
+}
+
+// Level 1: xor.
+#[derive(Clone, Copy)]
+pub struct XorModel;
+
+impl CipherModel for XorModel {
+    fn name(&self) -> String {
+        "xor".to_string()
+    }
+
+    fn key_bits(&self) -> f64 {
+        8f64
+    }
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        key[0].len()
+    }
+
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        let mut subs : Vec<[usize; 256]> = (0..key[0].len()).map(|_| [0usize; 256]).collect();
+        for p in 0..key[0].len() {
+            gen_xor_sub(key[0][p], &mut subs[p]);
+        }
+        subs
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String {
+        format!("x = {}", format_hex(&key[0][..]))
+    }
+
+    fn truncate_key(&self, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>> {
+        vec![key[0][..period].to_vec()]
+    }
+
+    fn find_key(&self, subs : &[[usize; 256]]) -> Option<Vec<Vec<u8>>> {
+        let keys : Vec<Option<u8>> = subs.iter().map(find_lvl1_key).collect();
+        if keys.iter().all(|k| k.is_some()) {
+            return Some(vec![keys.iter().map(|k| k.unwrap()).collect()]);
+        }
+        None
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>> {
+        vec![(0..l).map(|_| rng.gen_u8()).collect()]
+    }
+
+    fn break_key(&self, columns : &Columns, sample : &Sample, scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64 {
+        let results = search_columns(columns, |_, u| {
+            let mut sub = [0usize; 256];
+            let mut best = (0u8, f64::INFINITY);
+            for k in 0usize..256 {
+                gen_xor_sub(k as u8, &mut sub);
+                let s = scorer.distance(&sample.unigram, u, &sub);
+                if s < best.1 {
+                    best = (k as u8, s);
+                }
+            }
+            best
+        });
+        *key = vec![results.iter().map(|r| r.0).collect()];
+        let distances : Vec<f64> = results.iter().map(|r| r.1).collect();
+        mean_similarity(scorer, sample, &distances[..])
+    }
+
+    fn box_clone(&self) -> Box<dyn CipherModel> {
+        Box::new(*self)
+    }
+}
+
+// Level 2: xor then add.
+#[derive(Clone, Copy)]
+pub struct XorAddModel;
+
+impl CipherModel for XorAddModel {
+    fn name(&self) -> String {
+        "xor-add".to_string()
+    }
+
+    fn key_bits(&self) -> f64 {
+        16f64
+    }
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        level_pipeline("xor,add").key_length(key)
+    }
+
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        level_pipeline("xor,add").gen_key_subs(key)
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String {
+        format!("x = {} a = {}", format_hex(&key[0][..]), format_hex(&key[1][..]))
+    }
+
+    fn truncate_key(&self, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>> {
+        compact_xor_add_key("xor,add", key, period)
+    }
+
+    // Among equivalent keys, the one with the smallest x is chosen.
+    fn find_key(&self, subs : &[[usize; 256]]) -> Option<Vec<Vec<u8>>> {
+        let keys : Vec<Option<(u8, u8)>> = subs.iter().map(find_lvl2_key).collect();
+        if keys.iter().all(|k| k.is_some()) {
+            return Some(vec![keys.iter().map(|k| k.unwrap().0).collect(),
+                             keys.iter().map(|k| k.unwrap().1).collect()]);
+        }
+        None
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>> {
+        vec![(0..l).map(|_| rng.gen_u8()).collect(),
+             (0..l).map(|_| rng.gen_u8()).collect()]
+    }
+
+    fn break_key(&self, columns : &Columns, sample : &Sample, scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64 {
+        let results = search_columns(columns, |_, u| {
+            let mut sub = [0usize; 256];
+            let mut best = (0u8, 0u8, f64::INFINITY);
+            for x in 0usize..256 {
+                for a in 0usize..256 {
+                    gen_xor_add_sub(x as u8, a as u8, &mut sub);
+                    let s = scorer.distance(&sample.unigram, u, &sub);
+                    if s < best.2 {
+                        best = (x as u8, a as u8, s);
+                    }
+                }
+            }
+            best
+        });
+        *key = vec![results.iter().map(|r| r.0).collect(),
+                    results.iter().map(|r| r.1).collect()];
+        let distances : Vec<f64> = results.iter().map(|r| r.2).collect();
+        mean_similarity(scorer, sample, &distances[..])
+    }
+
+    fn box_clone(&self) -> Box<dyn CipherModel> {
+        Box::new(*self)
+    }
+}
+
+// Level 3: xor, add then mix.
+#[derive(Clone, Copy)]
+pub struct XorAddMixModel;
+
+impl CipherModel for XorAddMixModel {
+    fn name(&self) -> String {
+        "xor-add-mix".to_string()
+    }
+
+    fn key_bits(&self) -> f64 {
+        16f64 + (MIX_COUNT as f64).log2()
+    }
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        level_pipeline("xor,add,mix").key_length(key)
+    }
+
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        level_pipeline("xor,add,mix").gen_key_subs(key)
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String {
+        format!("x = {} a = {} m = {}", format_hex(&key[0][..]), format_hex(&key[1][..]),
+                format_mix_key(&key[2][..]))
+    }
+
+    fn truncate_key(&self, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>> {
+        compact_xor_add_key("xor,add,mix", key, period)
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>> {
+        let mut m : Vec<u8> = Vec::new();
+        for _ in 0..l {
+            let v = rng.gen_range(0, MIX_COUNT as usize) as u16;
+            m.push((v >> 8) as u8);
+            m.push((v & 0xff) as u8);
+        }
+        vec![(0..l).map(|_| rng.gen_u8()).collect(),
+             (0..l).map(|_| rng.gen_u8()).collect(),
+             m]
+    }
+
+    fn break_key(&self, columns : &Columns, sample : &Sample, scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64 {
+        let results = search_columns(columns, |_, u| {
+            let du = &sample.unigram;
+            let mut sub = [0usize; 256];
+            let mut best = (0u8, 0u8, 0u16, f64::INFINITY);
+            let mut candidates : Vec<Probabilistic<[u8; 2]>> = Vec::new();
+            for x in 0usize..256 {
+                for a in 0usize..256 {
+                    gen_xor_add_sub(x as u8, a as u8, &mut sub);
+                    let s = compute_hamming_var(du, u, &sub);
+                    candidates.push(Probabilistic{p : s, v : [x as u8, a as u8]});
+                }
+            }
+            candidates.sort_by(|a, b| {
+                if a.p < b.p { Ordering::Less }
+                else if a.p > b.p { Ordering::Greater }
+                else { Ordering::Equal }
+            });
+            for c in candidates.iter().take(40) {
+                if c.p > 0.01 || (scorer.bounded_by_hamming() && c.p > best.3) {
+                    break;
+                }
+                for m in 0u16..MIX_COUNT {
+                    gen_sub(c.v[0], c.v[1], m, &mut sub);
+                    let s = scorer.distance(du, u, &sub);
+                    if s < best.3 {
+                        best = (c.v[0], c.v[1], m, s);
+                    }
+                }
+            }
+            best
+        });
+        let mut m : Vec<u8> = Vec::new();
+        for r in results.iter() {
+            m.push((r.2 >> 8) as u8);
+            m.push((r.2 & 0xff) as u8);
+        }
+        *key = vec![results.iter().map(|r| r.0).collect(),
+                    results.iter().map(|r| r.1).collect(),
+                    m];
+        let distances : Vec<f64> = results.iter().map(|r| r.3).collect();
+        mean_similarity(scorer, sample, &distances[..])
+    }
+
+    fn box_clone(&self) -> Box<dyn CipherModel> {
+        Box::new(*self)
+    }
+}
+
+// Level 4: any substitution, each key position is the substitution table
+// matching the byte frequencies of the sample.
+#[derive(Clone, Copy)]
+pub struct FrequencyModel;
+
+impl CipherModel for FrequencyModel {
+    fn name(&self) -> String {
+        "frequency".to_string()
+    }
+
+    fn key_bits(&self) -> f64 {
+        256f64.log2() * 256f64
+    }
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        key.len()
+    }
+
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        let mut subs : Vec<[usize; 256]> = (0..key.len()).map(|_| [0usize; 256]).collect();
+        for p in 0..key.len() {
+            for i in 0usize..256 {
+                subs[p][i] = key[p][i] as usize;
+            }
+        }
+        subs
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String {
+        let tables : Vec<String> = key.iter().map(|k| format_hex(&k[..])).collect();
+        format!("s = {}", tables.join(" "))
+    }
+
+    fn truncate_key(&self, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>> {
+        key[..period].to_vec()
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>> {
+        (0..l).map(|_| {
+            let mut table : Vec<u8> = (0usize..256).map(|i| i as u8).collect();
+            rng.shuffle(&mut table[..]);
+            table
+        }).collect()
+    }
+
+    fn break_key(&self, columns : &Columns, sample : &Sample, _scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64 {
+        let mut su : Vec<Probabilistic<u8>> = (0..256).map(|_| Probabilistic {p : 0f64, v : 0u8}).collect();
+        key.clear();
+        for (i, s) in su.iter_mut().enumerate() {
+            s.v = i as u8;
+            s.p = sample.unigram[i];
+        }
+        su.sort_by( |a, b| {
+            if b.p < a.p { Ordering::Less }
+            else if b.p > a.p { Ordering::Greater }
+            else { Ordering::Equal }
+        });
+        for u in columns.unigram.iter() {
+            let mut unigram : Vec<Probabilistic<u8>> = (0..256).map(|i| Probabilistic {p : u[i], v : i as u8}).collect();
+            unigram.sort_by( |a, b| {
+                if b.p < a.p { Ordering::Less }
+                else if b.p > a.p { Ordering::Greater }
+                else { Ordering::Equal }
+            });
+            let mut table : Vec<u8> = std::iter::repeat_n(0u8, 256).collect();
+            for i in 0usize..256 {
+                table[su[i].v as usize] = unigram[i].v;
+            }
+            key.push(table);
+        }
+        0f64
+    }
+
+    fn box_clone(&self) -> Box<dyn CipherModel> {
+        Box::new(*self)
+    }
+}
+
+// Largest number of keys per position searched exhaustively for a pipeline,
+// the keys of its mix operation excluded.
+pub const MAX_PIPELINE_KEYS : u64 = 1 << 24;
+
+// Check that the key space of a pipeline can be searched, only the allowed
+// keys being tried.
+pub fn check_pipeline(pipeline : &Pipeline, constraints : &Constraints) -> Result<(), String> {
+    let mix = pipeline.ops.iter().position(|op| *op == Op::Mix);
+    if pipeline.ops.iter().filter(|op| **op == Op::Mix).count() > 1 {
+        return Err(format!("{} has more than one mix operation", pipeline.name()));
+    }
+    for p in 0..constraints.period() {
+        let mut allowed = constraints.allowed(pipeline, p);
+        if let Some(j) = mix {
+            allowed.remove(j);
+        }
+        let keys = product_size(&allowed[..]);
+        if keys > MAX_PIPELINE_KEYS {
+            return Err(format!("{} has too many keys to search ({} per byte)", pipeline.name(), keys));
+        }
+    }
+    Ok(())
+}
+
+// Any sequence of operations, see subcipher::pipeline. Only the keys allowed by
+// the constraints are searched.
+#[derive(Clone)]
+pub struct PipelineModel {
+    pub pipeline : Pipeline,
+    pub constraints : Constraints
+}
+
+impl CipherModel for PipelineModel {
+    fn name(&self) -> String {
+        self.pipeline.name()
+    }
+
+    fn key_bits(&self) -> f64 {
+        (self.pipeline.key_space() as f64).log2()
+    }
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        self.pipeline.key_length(key)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        check_pipeline(&self.pipeline, &self.constraints)
+    }
+
+    // The constraints are the same at positions p and p + l only when l is a
+    // multiple of their period.
+    fn key_period(&self) -> usize {
+        self.constraints.period()
+    }
+
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        self.pipeline.gen_key_subs(key)
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String {
+        self.pipeline.format_key(key)
+    }
+
+    fn truncate_key(&self, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>> {
+        self.pipeline.compact_key(&self.pipeline.truncate_key(key, period)[..])
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>> {
+        let l = extend_length(l, self.key_period());
+        let mut key = self.pipeline.new_key(l);
+        for p in 0..l {
+            let allowed = self.constraints.allowed(&self.pipeline, p);
+            let params : Vec<u16> = allowed.iter().map(|v| v[rng.gen_range(0, v.len())]).collect();
+            self.pipeline.set_params_at(&mut key[..], p, &params[..]);
+        }
+        key
+    }
+
+    fn break_key(&self, columns : &Columns, sample : &Sample, scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64 {
+        // A pipeline too large to search is aborted, like a column exceeding
+        // its budget.
+        if self.check().is_err() {
+            *key = self.pipeline.new_key(columns.length);
+            return 0f64;
+        }
+        let wide;
+        let columns = match extend_columns(columns, self.key_period()) {
+            Some(c) => {
+                wide = c;
+                &wide
+            },
+            None => columns
+        };
+        let pipeline = &self.pipeline;
+        let constraints = &self.constraints;
+        let results = search_columns(columns, |p, u| {
+            let allowed = constraints.allowed(pipeline, p);
+            match pipeline.ops.iter().position(|op| *op == Op::Mix) {
+                Some(j) => search_pipeline_mix(pipeline, j, &allowed[..], scorer, &sample.unigram, u),
+                None => search_pipeline(pipeline, &allowed[..], scorer, &sample.unigram, u)
+            }
+        });
+        *key = pipeline.new_key(columns.length);
+        for (p, r) in results.iter().enumerate() {
+            pipeline.set_params_at(&mut key[..], p, &r.0[..]);
+        }
+        let distances : Vec<f64> = results.iter().map(|r| r.1).collect();
+        mean_similarity(scorer, sample, &distances[..])
+    }
+
+    fn box_clone(&self) -> Box<dyn CipherModel> {
+        Box::new(self.clone())
+    }
+}
+
+// Number of combinations of the allowed values of each operation.
+fn product_size(allowed : &[Vec<u16>]) -> u64 {
+    allowed.iter().fold(1u64, |s, v| s * v.len() as u64)
+}
+
+// Parameters of the index-th combination of the allowed values.
+fn product_params(allowed : &[Vec<u16>], index : u64) -> Vec<u16> {
+    let mut params : Vec<u16> = Vec::new();
+    let mut r = index;
+    for v in allowed.iter().rev() {
+        params.push(v[(r % v.len() as u64) as usize]);
+        r /= v.len() as u64;
+    }
+    params.reverse();
+    params
+}
+
+// Try every allowed key of a pipeline without mix operation.
+fn search_pipeline(pipeline : &Pipeline, allowed : &[Vec<u16>], scorer : &dyn Scorer, du : &[f64; 256],
+                   u : &[f64; 256]) -> (Vec<u16>, f64) {
+    let mut sub = [0usize; 256];
+    let mut best = (product_params(allowed, 0), f64::INFINITY);
+    for i in 0..product_size(allowed) {
+        let params = product_params(allowed, i);
+        pipeline.gen_sub(&params[..], &mut sub);
+        let s = scorer.distance(du, u, &sub);
+        if s < best.1 {
+            best = (params, s);
+        }
+    }
+    best
+}
+
+// Search a pipeline whose operation j is a mix like level 3: a mix doesn't
+// change the hamming weight, so the operations before and after it are ranked
+// with the hamming weight distance and mixes are only tried for the best ones.
+fn search_pipeline_mix(pipeline : &Pipeline, j : usize, allowed : &[Vec<u16>], scorer : &dyn Scorer,
+                       du : &[f64; 256], u : &[f64; 256]) -> (Vec<u16>, f64) {
+    let pre = Pipeline {ops : pipeline.ops[..j].to_vec()};
+    let post = Pipeline {ops : pipeline.ops[j+1..].to_vec()};
+    let pre_allowed = &allowed[..j];
+    let post_allowed = &allowed[j+1..];
+    let post_size = product_size(post_allowed);
+    let mut pre_sub = [0usize; 256];
+    let mut post_sub = [0usize; 256];
+    let mut sub = [0usize; 256];
+    let mut inner = [0f64; 256];
+    let mut candidates : Vec<Probabilistic<u64>> = Vec::new();
+    for i in 0..product_size(pre_allowed) * post_size {
+        pre.gen_sub(&product_params(pre_allowed, i / post_size)[..], &mut pre_sub);
+        post.gen_sub(&product_params(post_allowed, i % post_size)[..], &mut post_sub);
+        // Distribution of the bytes between the mix and the operations after it.
+        for v in 0usize..256 {
+            inner[v] = u[post_sub[v]];
+        }
+        let s = compute_hamming_var(du, &inner, &pre_sub);
+        candidates.push(Probabilistic{p : s, v : i});
+    }
+    candidates.sort_by(|a, b| {
+        if a.p < b.p { Ordering::Less }
+        else if a.p > b.p { Ordering::Greater }
+        else { Ordering::Equal }
+    });
+    let mut best = (product_params(allowed, 0), f64::INFINITY);
+    for c in candidates.iter().take(40) {
+        if c.p > 0.01 || (scorer.bounded_by_hamming() && c.p > best.1) {
+            break;
+        }
+        let mut params = product_params(pre_allowed, c.v / post_size);
+        params.push(0u16);
+        params.extend(product_params(post_allowed, c.v % post_size));
+        for &m in allowed[j].iter() {
+            params[j] = m;
+            pipeline.gen_sub(&params[..], &mut sub);
+            let s = scorer.distance(du, u, &sub);
+            if s < best.1 {
+                best = (params.clone(), s);
+            }
+        }
+    }
+    best
+}
+
+// Largest number of input bytes analysed by the stream models, each byte is
+// deciphered with the 65536 possible keys and steps.
+pub const MAX_STREAM_BYTES : usize = 8192;
+
+// A single operation whose key byte at stream position i is a repeating key
+// byte stepped with i * d, see subcipher::pipeline::Step. The key is the
+// repeating key followed by d.
+#[derive(Clone)]
+pub struct StreamModel {
+    pub pipeline : Pipeline,
+    pub step : Step
+}
+
+impl CipherModel for StreamModel {
+    fn name(&self) -> String {
+        format!("{}-{}", self.pipeline.name(), self.step.name())
+    }
+
+    fn key_bits(&self) -> f64 {
+        16f64
+    }
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        key[0].len()
+    }
+
+    // The step wraps around after 256 positions.
+    fn subs_period(&self, key : &[Vec<u8>]) -> usize {
+        lcm(key[0].len(), 256)
+    }
+
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        self.pipeline.gen_stepped_subs(&key[..1], self.step, key[1][0])
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String {
+        format!("{} = {} {} = {:02x}", self.pipeline.ops[0].letter(), format_hex(&key[0][..]),
+                self.step.name(), key[1][0])
+    }
+
+    // The substitution tables repeat with the step, only the repeating key is
+    // reduced to its own period.
+    fn truncate_key(&self, key : &[Vec<u8>], _period : usize) -> Vec<Vec<u8>> {
+        vec![key[0][..period(&key[0])].to_vec(), key[1].clone()]
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>> {
+        vec![(0..l).map(|_| rng.gen_u8()).collect(), vec![rng.gen_u8()]]
+    }
+
+    // Each column is deciphered with every key byte and step, the step giving
+    // the best mean similarity over the columns is kept.
+    fn break_key(&self, columns : &Columns, sample : &Sample, scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64 {
+        let l = columns.length;
+        let mut segments : Vec<&[u8]> = Vec::new();
+        let mut n = 0usize;
+        for segment in columns.segments.iter() {
+            if n >= MAX_STREAM_BYTES {
+                break;
+            }
+            segments.push(&segment[..min(segment.len(), MAX_STREAM_BYTES - n)]);
+            n += segment.len();
+        }
+        let segments = &segments;
+        let inv : Vec<[usize; 256]> = (0usize..256).map(|k| {
+            let mut sub = [0usize; 256];
+            self.pipeline.gen_sub(&[k as u16], &mut sub);
+            inv_sub(&mut sub);
+            sub
+        }).collect();
+        let identity = identity_sub();
+        let step = self.step;
+        let results = search_columns(columns, |p, _| {
+            let mut best : Vec<(u8, f64)> = Vec::new();
+            for d in 0usize..256 {
+                let mut b = (0u8, f64::INFINITY);
+                for r in 0usize..256 {
+                    let mut freq = [0u64; 256];
+                    let mut sum = 0u64;
+                    for segment in segments.iter() {
+                        let mut i = p;
+                        while i < segment.len() {
+                            let k = step.apply(r as u8, i, d as u8);
+                            freq[inv[k as usize][segment[i] as usize]] += 1;
+                            sum += 1;
+                            i += l;
+                        }
+                    }
+                    let mut u = [0f64; 256];
+                    for v in 0usize..256 {
+                        u[v] = freq[v] as f64 / sum as f64;
+                    }
+                    let s = scorer.distance(&sample.unigram, &u, &identity);
+                    if s < b.1 {
+                        b = (r as u8, s);
+                    }
+                }
+                best.push(b);
+            }
+            best
+        });
+        let mut best_d = 0usize;
+        let mut best_score = -1f64;
+        for d in 0usize..256 {
+            let distances : Vec<f64> = results.iter().map(|r| r[d].1).collect();
+            let score = mean_similarity(scorer, sample, &distances[..]);
+            if score > best_score {
+                best_d = d;
+                best_score = score;
+            }
+        }
+        *key = vec![results.iter().map(|r| r[best_d].0).collect(), vec![best_d as u8]];
+        best_score
+    }
+
+    fn box_clone(&self) -> Box<dyn CipherModel> {
+        Box::new(self.clone())
+    }
+}
+
+// Xor, addition or subtraction of words, see subcipher::word. The key holds the
+// words as big endian numbers, its length in bytes being a whole number of
+// words.
+#[derive(Clone, Copy)]
+pub struct WordModel {
+    pub word : Word
+}
+
+impl CipherModel for WordModel {
+    fn name(&self) -> String {
+        self.word.name()
+    }
+
+    fn key_bits(&self) -> f64 {
+        8f64
+    }
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        key[0].len()
+    }
+
+    fn key_period(&self) -> usize {
+        self.word.width
+    }
+
+    // Carries make the bytes of a word depend on each other.
+    fn is_substitution(&self) -> bool {
+        self.word.op == WordOp::Xor
+    }
+
+    // Substitution of each byte of the key when ignoring the carries, which
+    // still identifies the key.
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        let w = self.word.width;
+        (0..key[0].len()).map(|p| {
+            let k = self.word.key_byte(&key[0][..], p / w, self.word.significance(p % w)) as usize;
+            let mut sub = [0usize; 256];
+            for (v, s) in sub.iter_mut().enumerate() {
+                *s = match self.word.op {
+                    WordOp::Xor => v ^ k,
+                    WordOp::Add => (v + k) & 0xff,
+                    WordOp::Sub => (v + 256 - k) & 0xff,
+                };
+            }
+            sub
+        }).collect()
+    }
+
+    fn encipher(&self, key : &[Vec<u8>], data : &[u8]) -> Vec<u8> {
+        let mut cipher = data.to_vec();
+        self.word.encrypt(&mut cipher[..], &key[0][..]);
+        cipher
+    }
+
+    fn decipher(&self, key : &[Vec<u8>], data : &[u8]) -> Vec<u8> {
+        let mut plain = data.to_vec();
+        self.word.decrypt(&mut plain[..], &key[0][..]);
+        plain
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String {
+        let words : Vec<String> = key[0].chunks(self.word.width).map(format_hex).collect();
+        format!("{} = {}", self.word.letter(), words.join(" "))
+    }
+
+    fn truncate_key(&self, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>> {
+        if period.is_multiple_of(self.word.width) {
+            vec![key[0][..period].to_vec()]
+        }
+        else {
+            key.to_vec()
+        }
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>> {
+        let w = self.word.width;
+        vec![(0..l.div_ceil(w) * w).map(|_| rng.gen_u8()).collect()]
+    }
+
+    // The key length is rounded up to a whole number of words. The least
+    // significant byte of each key word is searched first: its column is a
+    // plain byte substitution. Once its key byte is known, so is the
+    // proportion q of the bytes carrying into the next byte, whose column is
+    // then deciphered as the mixture of its bytes without and with a carry, and
+    // so on up to the most significant byte.
+    fn break_key(&self, columns : &Columns, sample : &Sample, scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64 {
+        let w = self.word.width;
+        // The columns of the bytes of each key word.
+        let wide;
+        let columns = match extend_columns(columns, w) {
+            Some(c) => {
+                wide = c;
+                &wide
+            },
+            None => columns
+        };
+        let lw = columns.length;
+        let words = lw / w;
+        *key = vec![std::iter::repeat_n(0u8, lw).collect()];
+        let identity = identity_sub();
+        let mut distances : Vec<f64> = Vec::new();
+        for j in 0..words {
+            if columns.segments.iter().all(|segment| segment.len() < (j + 1) * w) {
+                return 0f64;
+            }
+            let mut q = 0f64;
+            for s in 0..w {
+                let b = (0..w).find(|b| self.word.significance(*b) == s).unwrap();
+                let h = &columns.unigram[j * w + b];
+                let mut best = (0u8, f64::INFINITY);
+                for k in 0usize..256 {
+                    let mut u = [0f64; 256];
+                    for (c, f) in h.iter().enumerate() {
+                        u[self.word.op.decrypt_byte(c as u8, k as u8, 0).0 as usize] += (1f64 - q) * f;
+                        u[self.word.op.decrypt_byte(c as u8, k as u8, 1).0 as usize] += q * f;
+                    }
+                    let d = scorer.distance(&sample.unigram, &u, &identity);
+                    if d < best.1 {
+                        best = (k as u8, d);
+                    }
+                }
+                key[0][j * w + w - 1 - s] = best.0;
+                distances.push(best.1);
+                q = h.iter().enumerate().map(|(c, f)| {
+                    f * ((1f64 - q) * self.word.op.decrypt_byte(c as u8, best.0, 0).1 as f64 +
+                         q * self.word.op.decrypt_byte(c as u8, best.0, 1).1 as f64)
+                }).sum();
+            }
+        }
+        mean_similarity(scorer, sample, &distances[..])
+    }
+
+    fn box_clone(&self) -> Box<dyn CipherModel> {
+        Box::new(*self)
+    }
+}
@@ -0,0 +1,187 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::Ordering;
+
+use subcipher::gen_xor_sub;
+use super::{Columns, Sample, search_columns};
+use crate::scorer::{Scorer, rescale};
+
+// A key position is of low confidence when the second best printable key byte
+// scores within this margin of the best one.
+pub const CONFIDENCE_MARGIN : f64 = 0.05;
+
+// Number of words kept for each part of the two words phrases.
+const PHRASE_PARTS : usize = 5;
+
+pub fn is_printable(b : u8) -> bool {
+    (0x20..0x7f).contains(&b)
+}
+
+// Score of every printable xor key byte at each key position, the other bytes
+// being at 0.
+pub fn key_similarities(columns : &Columns, sample : &Sample, scorer : &dyn Scorer) -> Vec<[f64; 256]> {
+    let random = scorer.random_similarity(sample);
+    search_columns(columns, |_, u| {
+        let mut sub = [0usize; 256];
+        let mut similarities = [0f64; 256];
+        for k in 0x20u8..0x7f {
+            gen_xor_sub(k, &mut sub);
+            similarities[k as usize] = rescale(scorer.similarity(scorer.distance(&sample.unigram, u, &sub)), random);
+        }
+        similarities
+    })
+}
+
+// Key positions where the best key byte doesn't clearly beat the others.
+pub fn low_confidence(similarities : &[[f64; 256]]) -> Vec<usize> {
+    let mut positions : Vec<usize> = Vec::new();
+    for (p, s) in similarities.iter().enumerate() {
+        let mut best = (0f64, 0f64);
+        for &v in s[0x20..0x7f].iter() {
+            if v > best.0 {
+                best = (v, best.0);
+            }
+            else if v > best.1 {
+                best.1 = v;
+            }
+        }
+        if best.0 - best.1 < CONFIDENCE_MARGIN {
+            positions.push(p);
+        }
+    }
+    positions
+}
+
+// Key as a quoted string, the bytes which aren't printable being escaped.
+pub fn key_text(key : &[u8]) -> String {
+    let mut text = "\"".to_string();
+    for b in key.iter() {
+        match *b {
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            b if is_printable(b) => text.push(b as char),
+            b => text.push_str(&format!("\\x{:02x}", b)[..])
+        }
+    }
+    text.push('"');
+    text
+}
+
+// Read a wordlist, one word or phrase per line.
+pub fn parse_wordlist(data : &[u8]) -> Vec<Vec<u8>> {
+    let mut words : Vec<Vec<u8>> = Vec::new();
+    for line in data.split(|b| *b == b'\n') {
+        let mut w = line.to_vec();
+        while w.last().is_some_and(|b| *b == b'\r') {
+            w.pop();
+        }
+        if !w.is_empty() && w.iter().all(|b| is_printable(*b)) {
+            words.push(w);
+        }
+    }
+    words
+}
+
+// Words with their capitalized variant, passphrases often start with a
+// capital letter.
+fn variants(words : &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut all : Vec<Vec<u8>> = Vec::new();
+    for w in words.iter() {
+        all.push(w.clone());
+        if w[0] >= b'a' && w[0] <= b'z' {
+            let mut c = w.clone();
+            c[0] -= 0x20;
+            all.push(c);
+        }
+    }
+    all
+}
+
+// Sum of the similarities of word at offset in a passphrase of length n
+// repeating over the key positions.
+fn word_similarity(similarities : &[[f64; 256]], word : &[u8], offset : usize, n : usize) -> f64 {
+    let mut s = 0f64;
+    for p in 0..similarities.len() {
+        if p % n >= offset && p % n < offset + word.len() {
+            s += similarities[p][word[p % n - offset] as usize];
+        }
+    }
+    s
+}
+
+// Sort by decreasing similarity, dropping the duplicated texts.
+fn sort_best(candidates : &mut Vec<(f64, Vec<u8>)>) {
+    candidates.sort_by(|a, b| {
+        if b.0 < a.0 { Ordering::Less }
+        else if b.0 > a.0 { Ordering::Greater }
+        else { a.1.cmp(&b.1) }
+    });
+    let mut kept : Vec<(f64, Vec<u8>)> = Vec::new();
+    for c in candidates.drain(..) {
+        if !kept.iter().any(|k| k.1 == c.1) {
+            kept.push(c);
+        }
+    }
+    *candidates = kept;
+}
+
+// Passphrases of the wordlist, or made of two of its words, whose repetition
+// best matches the key similarities, as mean similarity and text. The
+// confident positions constrain the candidates and the words complete the
+// others.
+pub fn complete_key(similarities : &[[f64; 256]], words : &[Vec<u8>], limit : usize) -> Vec<(f64, Vec<u8>)> {
+    let l = similarities.len();
+    let words = variants(words);
+    let mut candidates : Vec<(f64, Vec<u8>)> = Vec::new();
+    for n in 1..l+1 {
+        if !l.is_multiple_of(n) {
+            continue;
+        }
+        for w in words.iter() {
+            if w.len() == n {
+                candidates.push((word_similarity(similarities, &w[..], 0, n), w.clone()));
+            }
+        }
+        for k in 1..n {
+            let best_parts = |offset : usize, len : usize| {
+                let mut parts : Vec<(f64, Vec<u8>)> = words.iter().filter(|w| w.len() == len)
+                    .map(|w| (word_similarity(similarities, &w[..], offset, n), w.clone())).collect();
+                sort_best(&mut parts);
+                parts.truncate(PHRASE_PARTS);
+                parts
+            };
+            let first = best_parts(0, k);
+            let second = best_parts(k, n - k);
+            for a in first.iter() {
+                for b in second.iter() {
+                    let mut phrase = a.1.clone();
+                    phrase.extend_from_slice(&b.1[..]);
+                    candidates.push((a.0 + b.0, phrase));
+                }
+            }
+        }
+    }
+    for c in candidates.iter_mut() {
+        c.0 /= l as f64;
+    }
+    sort_best(&mut candidates);
+    candidates.truncate(limit);
+    candidates
+}
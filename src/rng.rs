@@ -0,0 +1,59 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+// Small xorshift64* generator for the benchmarks and the random keys, seeded so
+// that runs can be reproduced.
+pub struct Rng {
+    state : u64
+}
+
+impl Rng {
+    pub fn new(seed : u64) -> Rng {
+        // The state must not be 0, mix the seed so that close seeds diverge.
+        let mut rng = Rng {state : seed ^ 0x9e3779b97f4a7c15};
+        if rng.state == 0 {
+            rng.state = 1;
+        }
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    pub fn gen_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    // Uniform value in [lo, hi).
+    pub fn gen_range(&mut self, lo : usize, hi : usize) -> usize {
+        lo + ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * (hi - lo) as f64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, values : &mut [T]) {
+        for i in (1..values.len()).rev() {
+            let j = self.gen_range(0, i + 1);
+            values.swap(i, j);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::Ordering;
+
+use super::{Probabilistic, Sample, identity_sub};
+
+// Distance between byte distributions used to rank the keys. Scorers are
+// shared between the threads searching each column.
+pub trait Scorer : Sync {
+    fn name(&self) -> String;
+
+    // Distance between the sample distribution u1 and the distribution u2 of
+    // the data deciphered with the substitution s, lower is better.
+    fn distance(&self, u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64;
+
+    // Map a distance to a similarity between 0 and 1, where 1 is a perfect
+    // match and 0 is the worst possible match.
+    fn similarity(&self, d : f64) -> f64;
+
+    // Whether the hamming weight distance is a lower bound of the distance,
+    // which lets the mix searches stop early.
+    fn bounded_by_hamming(&self) -> bool {
+        false
+    }
+
+    // Similarity of random bytes, uniformly distributed, to the sample.
+    fn random_similarity(&self, sample : &Sample) -> f64 {
+        self.similarity(self.distance(&sample.unigram, &[1f64 / 256f64; 256], &identity_sub()))
+    }
+
+    // Score of a distance: its similarity rescaled so that random bytes score
+    // 1/2 whatever the scorer, which makes the scores of different scorers
+    // comparable.
+    fn score(&self, sample : &Sample, d : f64) -> f64 {
+        rescale(self.similarity(d), self.random_similarity(sample))
+    }
+
+    // Score of some plaintext.
+    fn score_plaintext(&self, sample : &Sample, data : &[u8]) -> f64 {
+        let plain = Sample::from_data(data.to_vec());
+        let identity = identity_sub();
+        self.score(sample, self.distance(&sample.unigram, &plain.unigram, &identity))
+    }
+}
+
+// Rescale a similarity s so that the similarity of random bytes becomes 1/2,
+// a perfect match still scoring 1 and the worst possible match 0.
+pub fn rescale(s : f64, random : f64) -> f64 {
+    if random <= 0f64 || random >= 1f64 {
+        return s;
+    }
+    s.powf(0.5f64.ln() / random.ln())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Metric {L2, ChiSquared, KullbackLeibler, Bhattacharyya}
+
+// Additive smoothing of the sample distribution for the Kullback-Leibler
+// divergence, bytes absent from the sample would otherwise be impossible.
+const KL_SMOOTHING : f64 = 0.0001;
+
+impl Metric {
+    pub fn from_name(name : &str) -> Option<Metric> {
+        match name {
+            "l2" => Some(Metric::L2),
+            "chi2" => Some(Metric::ChiSquared),
+            "kl" => Some(Metric::KullbackLeibler),
+            "bhattacharyya" => Some(Metric::Bhattacharyya),
+            _ => None
+        }
+    }
+}
+
+impl Scorer for Metric {
+    fn name(&self) -> String {
+        match *self {
+            Metric::L2 => "l2".to_string(),
+            Metric::ChiSquared => "chi2".to_string(),
+            Metric::KullbackLeibler => "kl".to_string(),
+            Metric::Bhattacharyya => "bhattacharyya".to_string(),
+        }
+    }
+
+    fn distance(&self, u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
+        match *self {
+            Metric::L2 => compute_unigram_var(u1, u2, s),
+            Metric::ChiSquared => compute_unigram_chi2(u1, u2, s),
+            Metric::KullbackLeibler => compute_unigram_kl(u1, u2, s),
+            Metric::Bhattacharyya => compute_unigram_bhattacharyya(u1, u2, s),
+        }
+    }
+
+    fn similarity(&self, d : f64) -> f64 {
+        match *self {
+            // Distributions are at most at a squared distance of 2.
+            Metric::L2 => (1f64 - (d / 2f64).sqrt()).max(0f64),
+            Metric::ChiSquared => (1f64 - d / 2f64).max(0f64),
+            Metric::KullbackLeibler => (-d).exp(),
+            Metric::Bhattacharyya => (-d).exp(),
+        }
+    }
+
+    fn bounded_by_hamming(&self) -> bool {
+        *self == Metric::L2
+    }
+}
+
+fn compute_unigram_var(u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
+    let mut cost : f64 = 0f64;
+    for i in 0usize..256 {
+        let c = u1[i] - u2[s[i]];
+        cost += c*c;
+    }
+    cost
+}
+
+// Symmetric chi-squared distance, between 0 and 2.
+fn compute_unigram_chi2(u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
+    let mut cost : f64 = 0f64;
+    for i in 0usize..256 {
+        let t = u1[i] + u2[s[i]];
+        if t > 0f64 {
+            let c = u1[i] - u2[s[i]];
+            cost += c*c / t;
+        }
+    }
+    cost
+}
+
+// Kullback-Leibler divergence of the smoothed sample distribution from the
+// deciphered distribution. As the entropy of the data doesn't depend on the
+// substitution, minimizing it maximizes the log-likelihood of the data.
+fn compute_unigram_kl(u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
+    let mut cost : f64 = 0f64;
+    for i in 0usize..256 {
+        let q = u2[s[i]];
+        if q > 0f64 {
+            let p = (u1[i] + KL_SMOOTHING) / (1f64 + 256f64 * KL_SMOOTHING);
+            cost += q * (q / p).ln();
+        }
+    }
+    cost
+}
+
+// Bhattacharyya distance, the opposite of the logarithm of the Bhattacharyya
+// coefficient.
+fn compute_unigram_bhattacharyya(u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
+    let mut bc : f64 = 0f64;
+    for i in 0usize..256 {
+        bc += (u1[i] * u2[s[i]]).sqrt();
+    }
+    -bc.ln()
+}
+
+fn compute_hamming_weight(a : u8) -> u8 {
+    (a & 1u8) + ((a & 2u8) >> 1) + ((a & 4u8) >> 2) + ((a & 8u8) >> 3) +
+    ((a & 16u8) >> 4) + ((a & 32u8) >> 5) + ((a & 64u8) >> 6) + ((a & 128u8) >> 7)
+}
+
+pub fn compute_hamming_var(u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
+    let mut cost : f64 = 0f64;
+    let mut p1 : Vec<Probabilistic<u8>> = (0..256).map(|_| Probabilistic{p : 0f64, v : 0u8}).collect();
+    let mut p2 : Vec<Probabilistic<u8>> = (0..256).map(|_| Probabilistic{p : 0f64, v : 0u8}).collect();
+   for i in 0usize..256 {
+        p1[i].v = compute_hamming_weight(s[i] as u8);
+        p1[i].p = u1[i];
+        p2[i].v = compute_hamming_weight(i as u8);
+        p2[i].p = u2[i];
+    }
+    p1.sort_by( |a, b| {
+        if a.v < b.v { Ordering::Less }
+        else if a.v > b.v { Ordering::Greater }
+        else {
+            if a.p < b.p { Ordering::Less }
+            else if a.p > b.p { Ordering::Greater }
+            else { Ordering::Equal }
+        }
+    });
+    p2.sort_by( |a, b| {
+        if a.v < b.v { Ordering::Less }
+        else if a.v > b.v { Ordering::Greater }
+        else {
+            if a.p < b.p { Ordering::Less }
+            else if a.p > b.p { Ordering::Greater }
+            else { Ordering::Equal }
+        }
+    });
+    for i in 0usize..256 {
+        let c = p1[i].p - p2[i].p;
+        cost += c*c;
+    }
+    cost
+}
+
@@ -0,0 +1,122 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+use std::cmp::{max, min};
+
+use super::{Candidate, Sample, crack, decipher};
+use crate::model::CipherModel;
+use crate::scorer::Scorer;
+
+pub const DEFAULT_SEGMENT_WINDOW : usize = 1024;
+
+// Bytes from the start of the current segment used to estimate its key.
+const MAX_ESTIMATE_BYTES : usize = 16384;
+
+// A window starts a new segment when the key of the current segment scores
+// that much lower on it than its own key.
+const CHANGE_MARGIN : f64 = 0.1;
+
+// Additive smoothing of the sample distribution for the log-likelihood of the
+// bytes.
+const LL_SMOOTHING : f64 = 0.0001;
+
+pub struct Segment {
+    pub start : usize,
+    pub end : usize,
+    pub candidate : Option<Candidate>
+}
+
+// Decipher data with the key starting at position phase.
+fn decipher_at(c : &Candidate, data : &[u8], phase : usize) -> Vec<u8> {
+    let padded : Vec<u8> = std::iter::repeat_n(0u8, phase).chain(data.iter().copied()).collect();
+    decipher(&*c.model, &c.key, &padded[..], 0)[phase..].to_vec()
+}
+
+fn log_likelihood(sample : &Sample, data : &[u8]) -> Vec<f64> {
+    data.iter().map(|b| (sample.unigram[*b as usize] + LL_SMOOTHING).ln()).collect()
+}
+
+// Most likely position of the key change in data[lo..end], the first key
+// starting at seg and the second one being at position 0 at ws. Keys repeat,
+// so the second one is extended backwards to the bytes before ws.
+#[allow(clippy::too_many_arguments)]
+fn refine_boundary(a : &Candidate, b : &Candidate, data : &[u8], sample : &Sample, seg : usize, lo : usize,
+                   ws : usize, end : usize) -> usize {
+    let period = b.model.subs_period(&b.key);
+    let la = log_likelihood(sample, &decipher_at(a, &data[lo..end], lo - seg)[..]);
+    let lb = log_likelihood(sample, &decipher_at(b, &data[lo..end], (period - (ws - lo) % period) % period)[..]);
+    let mut best = (lo, f64::NEG_INFINITY);
+    let mut v = lb.iter().fold(0f64, |s, &l| s + l);
+    for t in 0..la.len()+1 {
+        if v > best.1 {
+            best = (lo + t, v);
+        }
+        if t < la.len() {
+            v += la[t] - lb[t];
+        }
+    }
+    best.0
+}
+
+// Offsets at which the key changes, starting with 0. The input is scanned by
+// windows: each window is deciphered with the key of the current segment,
+// estimated from its start, and with its own key. When the current key scores
+// clearly lower, the change is located inside this window and the previous one
+// as the position maximizing the likelihood of the bytes deciphered with the
+// current key before and the new key after.
+pub fn find_boundaries(models : &[Box<dyn CipherModel>], data : &[u8], sample : &Sample, scorer : &dyn Scorer,
+                       max_length : usize, window : usize) -> Vec<usize> {
+    let n = max(1, (data.len() + window / 2) / window);
+    let bounds : Vec<(usize, usize)> = (0..n).map(|j| (j * data.len() / n, (j + 1) * data.len() / n)).collect();
+    let mut starts = vec![0usize];
+    let mut seg = 0usize;
+    for j in 1..n {
+        let (ws, we) = bounds[j];
+        if ws - seg < window / 2 {
+            continue;
+        }
+        let current = crack(models, &[&data[seg..min(ws, seg + MAX_ESTIMATE_BYTES)]], sample, scorer,
+                            max_length, 0);
+        let own = crack(models, &[&data[ws..we]], sample, scorer, max_length, 0);
+        if let (Some(a), Some(b)) = (current, own) {
+            let cont = scorer.score_plaintext(sample, &decipher_at(&a, &data[ws..we], ws - seg)[..]);
+            let alone = scorer.score_plaintext(sample, &decipher_at(&b, &data[ws..we], 0)[..]);
+            if cont < alone - CHANGE_MARGIN {
+                let t = refine_boundary(&a, &b, data, sample, seg, max(seg, bounds[j-1].0), ws, we);
+                if t > seg {
+                    seg = t;
+                    starts.push(t);
+                }
+            }
+        }
+    }
+    starts
+}
+
+// Split the input where the key changes and break each segment separately.
+pub fn find_segments(models : &[Box<dyn CipherModel>], data : &[u8], sample : &Sample, scorer : &dyn Scorer,
+                     max_length : usize, window : usize) -> Vec<Segment> {
+    let mut starts = find_boundaries(models, data, sample, scorer, max_length, window);
+    starts.push(data.len());
+    (0..starts.len()-1).map(|i| {
+        let (start, end) = (starts[i], starts[i+1]);
+        Segment {start, end,
+                 candidate : crack(models, &[&data[start..end]], sample, scorer, max_length, 0)}
+    }).collect()
+}
@@ -0,0 +1,382 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2015 Charles Hubain <github@haxelion.eu>
+*/
+
+//! Byte substitution cipher primitives shared by SubBuster and DummyCrypt.
+//!
+//! A substitution table maps a plaintext byte to a ciphertext byte. Keys are
+//! made of three components: the xor bytes, the add bytes and the mix numbers
+//! stored as big endian 16 bits unsigned integers.
+//!
+//! A mix number is the Lehmer code of a bit permutation. It can also be written
+//! as the list of the destinations of bits 0 to 7, for example `[1,2,3,4,5,6,7,0]`
+//! is a left rotation by 1 and `[0,1,2,3,4,5,6,7]` is the identity.
+
+use std::cmp::max;
+
+pub mod pipeline;
+pub mod word;
+
+/// Number of bijective bit mix operations.
+pub const MIX_COUNT : u16 = 40320;
+
+pub fn gen_xor_sub(x : u8, sub : &mut [usize; 256]) {
+    for (i, s) in sub.iter_mut().enumerate() {
+        *s = i ^ x as usize;
+    }
+}
+
+pub fn gen_xor_add_sub(x : u8, a : u8, sub : &mut [usize; 256]) {
+    for (i, s) in sub.iter_mut().enumerate() {
+        *s = (i as u8 ^ x).wrapping_add(a) as usize;
+    }
+}
+
+/// Decode the bit mix number m into the destination of each bit, m being the
+/// Lehmer code of the permutation.
+pub fn mix_permutation(m : u16) -> [usize; 8] {
+    let c = [40320u16, 5040, 720, 120, 24, 6, 2, 1, 1];
+    let mut used = [false; 8];
+    let mut p = [0usize; 8];
+    for i in 0usize..8 {
+        p[i] = ((m%c[i])/c[i+1]+1) as usize;
+        for (j, u) in used.iter_mut().enumerate() {
+            if !*u {
+                p[i] -= 1;
+            }
+            if p[i] == 0 {
+                p[i] = j;
+                *u = true;
+                break;
+            }
+        }
+    }
+    p
+}
+
+/// Encode the destination of each bit into a bit mix number, checking that the
+/// destinations are a permutation of 0 to 7.
+pub fn mix_index(p : &[usize]) -> Result<u16, String> {
+    let c = [40320u16, 5040, 720, 120, 24, 6, 2, 1, 1];
+    if p.len() != 8 {
+        return Err(format!("a bit mix has 8 destinations, not {}", p.len()));
+    }
+    let mut used = [false; 8];
+    let mut m = 0u16;
+    for i in 0usize..8 {
+        if p[i] > 7 {
+            return Err(format!("bit destination {} is not between 0 and 7", p[i]));
+        }
+        if used[p[i]] {
+            return Err(format!("bit destination {} is used twice", p[i]));
+        }
+        let digit = used[..p[i]].iter().filter(|u| !**u).count() as u16;
+        m += digit * c[i+1];
+        used[p[i]] = true;
+    }
+    Ok(m)
+}
+
+/// Format a bit mix number as the list of the destinations of each bit.
+pub fn format_mix(m : u16) -> String {
+    let p = mix_permutation(m);
+    let d : Vec<String> = p.iter().map(|d| d.to_string()).collect();
+    format!("[{}]", d.join(","))
+}
+
+/// Format the mix component of a key as a list of destinations per position.
+pub fn format_mix_key(m : &[u8]) -> String {
+    (0..m.len()/2).map(|i| format_mix(mix_at(m, i))).collect()
+}
+
+/// Parse the mix component of a key, given either as a hex string of big endian
+/// 16 bits mix numbers or as destination lists like `[7,6,5,4,3,2,1,0][...]`.
+pub fn parse_mix(s : &str) -> Result<Vec<u8>, String> {
+    let mut mix = Vec::<u8>::new();
+    if s.trim_start().starts_with('[') {
+        for list in s.split(']').map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if !list.starts_with('[') {
+                return Err(format!("{} is not a list of bit destinations", list));
+            }
+            let mut p = Vec::<usize>::new();
+            for d in list[1..].split(',') {
+                match d.trim().parse() {
+                    Ok(v) => p.push(v),
+                    Err(_) => return Err(format!("{} is not a bit destination", d.trim()))
+                }
+            }
+            let m = mix_index(&p)?;
+            mix.push((m >> 8) as u8);
+            mix.push((m & 0xff) as u8);
+        }
+    }
+    else {
+        mix = parse_hex(s)?;
+        if mix.len() & 1 == 1 {
+            return Err(format!("{} is not made of 16 bits mix numbers", s));
+        }
+        for i in 0..mix.len()/2 {
+            if mix_at(&mix, i) >= MIX_COUNT {
+                return Err(format!("mix number {} is not lower than {}", mix_at(&mix, i), MIX_COUNT));
+            }
+        }
+    }
+    Ok(mix)
+}
+
+/// Substitution table of MIX(ADD(XOR(b, x), a), m).
+pub fn gen_sub(x : u8, a : u8, m : u16, sub : &mut [usize; 256]) {
+    let p = mix_permutation(m);
+    for (i, s) in sub.iter_mut().enumerate() {
+        let b = (i as u8 ^ x).wrapping_add(a);
+        let mut r = 0u8;
+        for (j, d) in p.iter().enumerate() {
+            r |= ((b >> j) & 1) << d;
+        }
+        *s = r as usize;
+    }
+}
+
+/// Invert a substitution table in place.
+pub fn inv_sub(sub : &mut [usize; 256]) {
+    let c = *sub;
+    for (i, s) in c.iter().enumerate() {
+        sub[*s] = i;
+    }
+}
+
+/// Parse a hex string of bytes.
+pub fn parse_hex(s : &str) -> Result<Vec<u8>, String> {
+    let digits : Vec<char> = s.chars().collect();
+    if digits.len() & 1 == 1 {
+        return Err(format!("{} has an odd number of digits", s));
+    }
+    let mut bytes = Vec::<u8>::new();
+    for pair in digits.chunks(2) {
+        match (pair[0].to_digit(16), pair[1].to_digit(16)) {
+            (Some(h), Some(l)) => bytes.push((h * 16 + l) as u8),
+            _ => return Err(format!("{} contains non hexadecimal characters", s))
+        }
+    }
+    Ok(bytes)
+}
+
+/// Mix number of position i of the mix key component.
+pub fn mix_at(m : &[u8], i : usize) -> u16 {
+    ((m[2*i] as u16) << 8) + m[2*i+1] as u16
+}
+
+/// Pad the xor, add and mix components of a key with zeroes to the same
+/// number of elements, at least one.
+pub fn pad_key(key : &mut Vec<Vec<u8>>) {
+    key.resize(3, Vec::new());
+    let length = max(1, max(key[0].len(), max(key[1].len(), key[2].len()/2)));
+    key[0].resize(length, 0u8);
+    key[1].resize(length, 0u8);
+    key[2].resize(length*2, 0u8);
+}
+
+/// Substitution tables of each position of a padded key.
+pub fn gen_key_subs(key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+    let mut subs = Vec::<[usize; 256]>::new();
+    for i in 0..key[0].len() {
+        subs.push([0usize; 256]);
+        gen_sub(key[0][i], key[1][i], mix_at(&key[2], i), &mut subs[i]);
+    }
+    subs
+}
+
+/// Substitute each byte of the buffer with the table of its position, the
+/// tables wrapping around when the buffer is bigger than the key.
+pub fn apply_subs(buffer : &mut [u8], subs : &[[usize; 256]]) {
+    for i in 0..buffer.len() {
+        buffer[i] = subs[i%subs.len()][buffer[i] as usize] as u8;
+    }
+}
+
+/// Greatest common divisor of a and b.
+pub fn gcd(a : usize, b : usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Least common multiple of a and b, the number of positions after which keys
+/// of these lengths repeat together.
+pub fn lcm(a : usize, b : usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+/// Smallest period of the values, which divides their number.
+pub fn period<T : PartialEq>(values : &[T]) -> usize {
+    let l = values.len();
+    (1..l).find(|&d| l.is_multiple_of(d) && (d..l).all(|i| values[i] == values[i % d])).unwrap_or(l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn is_bijective(sub : &[usize; 256]) -> bool {
+        let mut seen = [false; 256];
+        for s in sub.iter() {
+            if *s > 255 || seen[*s] {
+                return false;
+            }
+            seen[*s] = true;
+        }
+        true
+    }
+
+    fn check_inverse(x : u8, a : u8, m : u16) {
+        let mut sub = [0usize; 256];
+        gen_sub(x, a, m, &mut sub);
+        assert!(is_bijective(&sub));
+        let mut inv = sub;
+        inv_sub(&mut inv);
+        for i in 0usize..256 {
+            assert_eq!(inv[sub[i]], i);
+            assert_eq!(sub[inv[i]], i);
+        }
+    }
+
+    #[test]
+    fn mix_numbers_are_distinct_permutations() {
+        let mut seen = HashSet::new();
+        for m in 0..MIX_COUNT {
+            let p = mix_permutation(m);
+            let mut used = [false; 8];
+            for j in 0usize..8 {
+                assert!(!used[p[j]]);
+                used[p[j]] = true;
+            }
+            assert!(seen.insert(p));
+        }
+    }
+
+    #[test]
+    fn decrypt_inverts_encrypt_for_all_xor_add() {
+        for x in 0usize..256 {
+            for a in 0usize..256 {
+                for &m in [0u16, 1, 5913, 23616, MIX_COUNT-1].iter() {
+                    check_inverse(x as u8, a as u8, m);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decrypt_inverts_encrypt_for_all_mix() {
+        for m in 0..MIX_COUNT {
+            check_inverse(0x13, 0x37, m);
+        }
+    }
+
+    #[test]
+    fn breaker_tables_match_encryptor() {
+        let mut sub = [0usize; 256];
+        let mut fast = [0usize; 256];
+        for x in 0usize..256 {
+            gen_sub(x as u8, 0, 0, &mut sub);
+            gen_xor_sub(x as u8, &mut fast);
+            assert!(sub[..] == fast[..]);
+            for a in 0usize..256 {
+                gen_sub(x as u8, a as u8, 0, &mut sub);
+                gen_xor_add_sub(x as u8, a as u8, &mut fast);
+                assert!(sub[..] == fast[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn encrypt_buffer_matches_tables() {
+        let mut key = vec![parse_hex("13374242").unwrap(), parse_hex("deadbeef").unwrap(),
+                           parse_hex("0102030405060708").unwrap()];
+        pad_key(&mut key);
+        let plain : Vec<u8> = (0usize..1000).map(|i| (i * 7) as u8).collect();
+        let mut buffer = plain.clone();
+        let mut subs = gen_key_subs(&key);
+        apply_subs(&mut buffer, &subs);
+        let mut sub = [0usize; 256];
+        for i in 0..plain.len() {
+            gen_sub(key[0][i%4], key[1][i%4], mix_at(&key[2], i%4), &mut sub);
+            assert_eq!(buffer[i] as usize, sub[plain[i] as usize]);
+        }
+        for s in subs.iter_mut() {
+            inv_sub(s);
+        }
+        apply_subs(&mut buffer, &subs);
+        assert_eq!(buffer, plain);
+    }
+
+    #[test]
+    fn documented_rotations() {
+        let rol = [5913u16, 11824, 17730, 23616, 29400, 34560, 35280];
+        let mut sub = [0usize; 256];
+        for (r, m) in rol.iter().enumerate() {
+            gen_sub(0, 0, *m, &mut sub);
+            for (i, s) in sub.iter().enumerate() {
+                assert_eq!(*s as u8, (i as u8).rotate_left(r as u32 + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn mix_notation_round_trip() {
+        for m in 0..MIX_COUNT {
+            assert_eq!(mix_index(&mix_permutation(m)), Ok(m));
+            let bytes = vec![(m >> 8) as u8, (m & 0xff) as u8];
+            assert_eq!(parse_mix(&format_mix(m)), Ok(bytes));
+        }
+        assert_eq!(format_mix(0), "[0,1,2,3,4,5,6,7]");
+        assert_eq!(format_mix(5913), "[1,2,3,4,5,6,7,0]");
+        assert_eq!(parse_mix("[1,2,3,4,5,6,7,0] [0, 1, 2, 3, 4, 5, 6, 7]"), Ok(vec![0x17, 0x19, 0, 0]));
+        assert_eq!(format_mix_key(&[0x17, 0x19, 0, 0]), "[1,2,3,4,5,6,7,0][0,1,2,3,4,5,6,7]");
+    }
+
+    #[test]
+    fn invalid_mixes() {
+        assert!(parse_mix("[0,1,2,3,4,5,6,8]").is_err());
+        assert!(parse_mix("[0,1,2,3,4,5,6,6]").is_err());
+        assert!(parse_mix("[0,1,2,3,4,5,6]").is_err());
+        assert!(parse_mix("[0,1,2,3,4,5,6,x]").is_err());
+        assert!(parse_mix("9d80").is_err());
+        assert!(parse_mix("010203").is_err());
+        assert_eq!(parse_mix("9d7f"), Ok(vec![0x9d, 0x7f]));
+    }
+
+    #[test]
+    fn key_padding() {
+        let mut key = vec![parse_hex("1337").unwrap(), Vec::new(), parse_hex("0001").unwrap()];
+        pad_key(&mut key);
+        assert_eq!(key[0], vec![0x13, 0x37]);
+        assert_eq!(key[1], vec![0, 0]);
+        assert_eq!(key[2], vec![0, 1, 0, 0]);
+        assert!(parse_hex("13z7").is_err());
+        assert!(parse_hex("133").is_err());
+    }
+
+    #[test]
+    fn periods() {
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(lcm(4, 6), 12);
+        assert_eq!(lcm(3, 256), 768);
+        assert_eq!(period(&[1, 2, 1, 2, 1, 2]), 2);
+        assert_eq!(period(&[1, 2, 1, 2, 1]), 5);
+        assert_eq!(period(&[7u8; 4]), 1);
+    }
+}
@@ -0,0 +1,562 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2015 Charles Hubain <github@haxelion.eu>
+*/
+
+//! Byte substitutions described as a sequence of primitive operations.
+//!
+//! A pipeline like `xor,add,mix` applies each operation in order to the
+//! plaintext byte, each operation taking its own key component. Mix keys are
+//! stored as big endian 16 bits numbers, the other ones as single bytes.
+//! Multipliers have to be odd for the substitution to be invertible. The null
+//! preserving xor leaves the bytes equal to 0 or to its key unchanged, which
+//! is still a bijection as xoring swaps them.
+
+use std::cmp::max;
+use super::{format_mix_key, lcm, mix_at, mix_permutation, parse_hex, parse_mix, period, MIX_COUNT};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Op {Xor, Add, Sub, Rol, Mix, Mul, Nxor}
+
+impl Op {
+    pub fn from_name(name : &str) -> Option<Op> {
+        match name {
+            "xor" => Some(Op::Xor),
+            "add" => Some(Op::Add),
+            "sub" => Some(Op::Sub),
+            "rol" => Some(Op::Rol),
+            "mix" => Some(Op::Mix),
+            "mul" => Some(Op::Mul),
+            "nxor" => Some(Op::Nxor),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Op::Xor => "xor",
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Rol => "rol",
+            Op::Mix => "mix",
+            Op::Mul => "mul",
+            Op::Nxor => "nxor",
+        }
+    }
+
+    /// Letter naming the key component of the operation.
+    pub fn letter(&self) -> char {
+        match *self {
+            Op::Xor => 'x',
+            Op::Add => 'a',
+            Op::Sub => 's',
+            Op::Rol => 'r',
+            Op::Mix => 'm',
+            Op::Mul => 'c',
+            Op::Nxor => 'n',
+        }
+    }
+
+    /// Number of possible keys for one position.
+    pub fn key_space(&self) -> u32 {
+        match *self {
+            Op::Rol => 8,
+            Op::Mix => MIX_COUNT as u32,
+            Op::Mul => 128,
+            _ => 256
+        }
+    }
+
+    /// Parameter of the index-th key of the key space of one position.
+    pub fn param(&self, index : u32) -> u16 {
+        match *self {
+            Op::Mul => (2 * index + 1) as u16,
+            _ => index as u16
+        }
+    }
+
+    /// Number of key bytes for one position.
+    pub fn key_size(&self) -> usize {
+        match *self {
+            Op::Mix => 2,
+            _ => 1
+        }
+    }
+
+    /// Parse the key component of the operation, mixes accept the destination
+    /// list notation.
+    pub fn parse_key(&self, s : &str) -> Result<Vec<u8>, String> {
+        let k = match *self {
+            Op::Mix => parse_mix(s)?,
+            _ => parse_hex(s)?
+        };
+        if *self == Op::Rol {
+            if let Some(r) = k.iter().find(|r| **r > 7) {
+                return Err(format!("rotation {} is not between 0 and 7", r));
+            }
+        }
+        if *self == Op::Mul {
+            if let Some(c) = k.iter().find(|c| **c & 1 == 0) {
+                return Err(format!("multiplier {:02x} is not odd", c));
+            }
+        }
+        Ok(k)
+    }
+
+    pub fn format_key(&self, k : &[u8]) -> String {
+        match *self {
+            Op::Mix => format_mix_key(k),
+            _ => k.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+    }
+}
+
+/// Position dependent change of the key of the first operation: at stream
+/// position i, the key byte is incremented by (Inc) or xored with (Idx) i * d.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Step {Inc, Idx}
+
+impl Step {
+    pub fn from_name(name : &str) -> Option<Step> {
+        match name {
+            "inc" => Some(Step::Inc),
+            "idx" => Some(Step::Idx),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Step::Inc => "inc",
+            Step::Idx => "idx",
+        }
+    }
+
+    /// Key byte k at stream position i.
+    pub fn apply(&self, k : u8, i : usize, d : u8) -> u8 {
+        let s = (i as u8).wrapping_mul(d);
+        match *self {
+            Step::Inc => k.wrapping_add(s),
+            Step::Idx => k ^ s,
+        }
+    }
+}
+
+/// Largest number of top bits searched when balancing a chain.
+const MAX_TOP_BITS : usize = 16;
+
+/// Reassign the top bits of the parameters of operations j to k (excluded),
+/// which only matter through their parity at each position, so that each
+/// operation's top bits repeat with the period of its other bits. The top bits
+/// of all the operations but the last are searched, the last one's follow from
+/// the parity.
+fn balance_top_bits(params : &mut [Vec<u16>], j : usize, k : usize) {
+    let l = params.len();
+    let parity : Vec<u16> = params.iter().map(|p| p[j..k].iter().fold(0, |t, v| t ^ (v >> 7))).collect();
+    let periods : Vec<usize> = (j..k).map(|u| {
+        let low : Vec<u16> = params.iter().map(|p| p[u] & 0x7f).collect();
+        period(&low)
+    }).collect();
+    let bits : usize = periods[..k - j - 1].iter().sum();
+    if bits > MAX_TOP_BITS {
+        return;
+    }
+    let last = periods[k - j - 1];
+    for mask in 0u32..1 << bits {
+        // Top bit of operation u at position i.
+        let top = |u : usize, i : usize| -> u16 {
+            let offset : usize = periods[..u].iter().sum();
+            ((mask >> (offset + i % periods[u])) & 1) as u16
+        };
+        let last_top : Vec<u16> = (0..l).map(|i| (0..k - j - 1).fold(parity[i], |t, u| t ^ top(u, i))).collect();
+        if (last..l).all(|i| last_top[i] == last_top[i % last]) {
+            for (i, p) in params.iter_mut().enumerate() {
+                for u in 0..k - j - 1 {
+                    p[j + u] = (p[j + u] & 0x7f) | (top(u, i) << 7);
+                }
+                p[k - 1] = (p[k - 1] & 0x7f) | (last_top[i] << 7);
+            }
+            return;
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pipeline {
+    pub ops : Vec<Op>
+}
+
+impl Pipeline {
+    /// Parse a comma or dash separated list of operations like `xor,add,mix`.
+    /// `affine` stands for `mul,add`.
+    pub fn parse(s : &str) -> Result<Pipeline, String> {
+        if s == "affine" {
+            return Ok(Pipeline { ops : vec![Op::Mul, Op::Add] });
+        }
+        let mut ops = Vec::<Op>::new();
+        for name in s.split([',', '-']).map(|n| n.trim()) {
+            match Op::from_name(name) {
+                Some(op) => ops.push(op),
+                None => return Err(format!("{} is not an operation, use xor, add, sub, rol, mix, mul or nxor", name))
+            }
+        }
+        Ok(Pipeline { ops })
+    }
+
+    pub fn name(&self) -> String {
+        let names : Vec<&str> = self.ops.iter().map(|op| op.name()).collect();
+        names.join("-")
+    }
+
+    /// Number of possible keys for one position.
+    pub fn key_space(&self) -> u64 {
+        self.ops.iter().fold(1u64, |s, op| s * op.key_space() as u64)
+    }
+
+    /// Parameters of each operation of the index-th key of the key space.
+    pub fn index_params(&self, index : u64) -> Vec<u16> {
+        let mut params = Vec::<u16>::new();
+        let mut r = index;
+        for op in self.ops.iter().rev() {
+            params.push(op.param((r % op.key_space() as u64) as u32));
+            r /= op.key_space() as u64;
+        }
+        params.reverse();
+        params
+    }
+
+    /// Substitution table of the pipeline with the given parameter for each
+    /// operation.
+    pub fn gen_sub(&self, params : &[u16], sub : &mut [usize; 256]) {
+        let perms : Vec<[usize; 8]> = self.ops.iter().zip(params.iter()).map(|(op, k)| {
+            if *op == Op::Mix { mix_permutation(*k) } else { [0usize; 8] }
+        }).collect();
+        for (i, s) in sub.iter_mut().enumerate() {
+            let mut b = i as u8;
+            for (j, op) in self.ops.iter().enumerate() {
+                b = match *op {
+                    Op::Xor => b ^ params[j] as u8,
+                    Op::Add => b.wrapping_add(params[j] as u8),
+                    Op::Sub => b.wrapping_sub(params[j] as u8),
+                    Op::Rol => b.rotate_left(params[j] as u32),
+                    Op::Mul => b.wrapping_mul(params[j] as u8),
+                    Op::Nxor => {
+                        let k = params[j] as u8;
+                        if b == 0 || b == k { b } else { b ^ k }
+                    },
+                    Op::Mix => {
+                        let mut r = 0u8;
+                        for (k, d) in perms[j].iter().enumerate() {
+                            r |= ((b >> k) & 1) << d;
+                        }
+                        r
+                    }
+                };
+            }
+            *s = b as usize;
+        }
+    }
+
+    /// Identity key for l positions, made of zeroes except for the
+    /// multipliers which are ones.
+    pub fn new_key(&self, l : usize) -> Vec<Vec<u8>> {
+        self.ops.iter().map(|op| vec![op.param(0) as u8; l * op.key_size()]).collect()
+    }
+
+    /// Number of positions of the key. Each component repeats with its own
+    /// period, so this is the least common multiple of their lengths.
+    pub fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        self.ops.iter().zip(key.iter()).fold(1, |l, (op, k)| {
+            lcm(l, k.len() / op.key_size())
+        })
+    }
+
+    /// Parameters of position i, each component wrapping around.
+    pub fn params_at(&self, key : &[Vec<u8>], i : usize) -> Vec<u16> {
+        self.ops.iter().enumerate().map(|(j, op)| {
+            let i = i % (key[j].len() / op.key_size());
+            match *op {
+                Op::Mix => mix_at(&key[j], i),
+                _ => key[j][i] as u16
+            }
+        }).collect()
+    }
+
+    pub fn set_params_at(&self, key : &mut [Vec<u8>], i : usize, params : &[u16]) {
+        for (j, op) in self.ops.iter().enumerate() {
+            match *op {
+                Op::Mix => {
+                    key[j][2*i] = (params[j] >> 8) as u8;
+                    key[j][2*i+1] = (params[j] & 0xff) as u8;
+                },
+                _ => key[j][i] = params[j] as u8
+            }
+        }
+    }
+
+    /// Pad the key components with the identity key to the same number of
+    /// positions, at least one.
+    pub fn pad_key(&self, key : &mut Vec<Vec<u8>>) {
+        key.resize(self.ops.len(), Vec::new());
+        let length = self.ops.iter().zip(key.iter()).fold(1, |l, (op, k)| max(l, k.len() / op.key_size()));
+        for (op, k) in self.ops.iter().zip(key.iter_mut()) {
+            k.resize(length * op.key_size(), op.param(0) as u8);
+        }
+    }
+
+    /// Give the missing components the identity key of one position, the
+    /// other components keeping their own period.
+    pub fn fill_key(&self, key : &mut Vec<Vec<u8>>) {
+        key.resize(self.ops.len(), Vec::new());
+        for (op, k) in self.ops.iter().zip(key.iter_mut()) {
+            if k.is_empty() {
+                k.resize(op.key_size(), op.param(0) as u8);
+            }
+        }
+    }
+
+    /// Key made of the first l positions of the key, all its components
+    /// having l positions.
+    pub fn truncate_key(&self, key : &[Vec<u8>], l : usize) -> Vec<Vec<u8>> {
+        let mut t = self.new_key(l);
+        for i in 0..l {
+            self.set_params_at(&mut t, i, &self.params_at(key, i));
+        }
+        t
+    }
+
+    /// Shortest key producing the same substitution tables, each component
+    /// being reduced to its own period. Xoring, adding or subtracting 0x80 are
+    /// the same, so the top bits of consecutive xor, add and sub keys are
+    /// first reassigned to make the components periodic when possible.
+    pub fn compact_key(&self, key : &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let l = self.key_length(key);
+        let mut params : Vec<Vec<u16>> = (0..l).map(|i| self.params_at(key, i)).collect();
+        let mut j = 0;
+        while j < self.ops.len() {
+            let mut k = j;
+            while k < self.ops.len() && [Op::Xor, Op::Add, Op::Sub].contains(&self.ops[k]) {
+                k += 1;
+            }
+            if k - j >= 2 {
+                balance_top_bits(&mut params, j, k);
+            }
+            j = k + 1;
+        }
+        let compact : Vec<Vec<u8>> = self.ops.iter().enumerate().map(|(j, op)| {
+            let values : Vec<u16> = params.iter().map(|p| p[j]).collect();
+            let mut c = Vec::<u8>::new();
+            for v in values[..period(&values)].iter() {
+                match *op {
+                    Op::Mix => c.extend([(*v >> 8) as u8, (*v & 0xff) as u8].iter()),
+                    _ => c.push(*v as u8)
+                }
+            }
+            c
+        }).collect();
+        let subs = self.gen_key_subs(key);
+        let compact_subs = self.gen_key_subs(&compact);
+        if (0..l).all(|i| subs[i][..] == compact_subs[i % compact_subs.len()][..]) {
+            compact
+        }
+        else {
+            self.truncate_key(key, l)
+        }
+    }
+
+    /// Substitution tables of each position of a key.
+    pub fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        let mut subs = Vec::<[usize; 256]>::new();
+        for i in 0..self.key_length(key) {
+            subs.push([0usize; 256]);
+            self.gen_sub(&self.params_at(key, i), &mut subs[i]);
+        }
+        subs
+    }
+
+    /// Substitution tables of a padded key whose first operation is stepped
+    /// with d. They repeat every lcm(l, 256) positions.
+    pub fn gen_stepped_subs(&self, key : &[Vec<u8>], step : Step, d : u8) -> Vec<[usize; 256]> {
+        let l = self.key_length(key);
+        let mut subs = Vec::<[usize; 256]>::new();
+        for i in 0..lcm(l, 256) {
+            let mut params = self.params_at(key, i % l);
+            params[0] = step.apply(params[0] as u8, i, d) as u16;
+            subs.push([0usize; 256]);
+            self.gen_sub(&params, &mut subs[i]);
+        }
+        subs
+    }
+
+    pub fn format_key(&self, key : &[Vec<u8>]) -> String {
+        let components : Vec<String> = self.ops.iter().zip(key.iter()).map(|(op, k)| {
+            format!("{} = {}", op.letter(), op.format_key(k))
+        }).collect();
+        components.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{gen_sub, inv_sub};
+
+    #[test]
+    fn independent_periods() {
+        let p = Pipeline::parse("xor,add,mix").unwrap();
+        let mut key = vec![vec![0x80, 0x01, 0x82, 0x03], vec![0x10, 0x91, 0x12], Vec::new()];
+        p.fill_key(&mut key);
+        assert_eq!(p.key_length(&key), 12);
+        assert_eq!(p.params_at(&key, 5), vec![0x01, 0x12, 0]);
+        let full = p.truncate_key(&key, 12);
+        assert_eq!(full[0].len(), 12);
+        assert_eq!(p.gen_key_subs(&full), p.gen_key_subs(&key));
+        assert_eq!(p.compact_key(&full), key);
+        // The xor top bits moved to the add key give the same tables.
+        let mut moved = full.clone();
+        let (x, rest) = moved.split_at_mut(1);
+        for (x, a) in x[0].iter_mut().zip(rest[0].iter_mut()) {
+            if *x & 0x80 != 0 {
+                *x ^= 0x80;
+                *a = a.wrapping_add(0x80);
+            }
+        }
+        assert_eq!(p.gen_key_subs(&moved), p.gen_key_subs(&key));
+        let compact = p.compact_key(&moved);
+        assert_eq!(p.key_length(&compact), 12);
+        assert_eq!(compact[0].len(), 4);
+        assert_eq!(compact[1].len(), 3);
+        assert_eq!(p.gen_key_subs(&compact), p.gen_key_subs(&key));
+    }
+
+    #[test]
+    fn xor_add_mix_matches_gen_sub() {
+        let p = Pipeline::parse("xor,add,mix").unwrap();
+        let mut sub = [0usize; 256];
+        let mut expected = [0usize; 256];
+        for &(x, a, m) in [(0u8, 0u8, 0u16), (0x13, 0x37, 5913), (0xff, 0x80, MIX_COUNT-1), (0x42, 0xde, 23616)].iter() {
+            p.gen_sub(&[x as u16, a as u16, m], &mut sub);
+            gen_sub(x, a, m, &mut expected);
+            assert!(sub[..] == expected[..]);
+        }
+    }
+
+    #[test]
+    fn primitive_operations() {
+        let mut sub = [0usize; 256];
+        Pipeline::parse("rol").unwrap().gen_sub(&[3], &mut sub);
+        assert_eq!(sub[0x81], 0x0c);
+        Pipeline::parse("sub").unwrap().gen_sub(&[3], &mut sub);
+        assert_eq!(sub[1], 0xfe);
+        Pipeline::parse("add-xor").unwrap().gen_sub(&[1, 0xff], &mut sub);
+        assert_eq!(sub[0x10], 0xee);
+    }
+
+    #[test]
+    fn decrypt_inverts_encrypt() {
+        let p = Pipeline::parse("sub,xor,rol,mix,add").unwrap();
+        let mut sub = [0usize; 256];
+        for index in (0..p.key_space()).step_by(7919 * 7907) {
+            let params = p.index_params(index);
+            p.gen_sub(&params, &mut sub);
+            let mut inv = sub;
+            inv_sub(&mut inv);
+            for i in 0usize..256 {
+                assert_eq!(inv[sub[i]], i);
+            }
+        }
+    }
+
+    #[test]
+    fn multiplication() {
+        let p = Pipeline::parse("affine").unwrap();
+        assert_eq!(p.name(), "mul-add");
+        assert_eq!(p.key_space(), 128 * 256);
+        assert_eq!(p.index_params(0), vec![1, 0]);
+        assert_eq!(p.index_params(3 * 256 + 7), vec![7, 7]);
+        let mut sub = [0usize; 256];
+        p.gen_sub(&[3, 0x10], &mut sub);
+        assert_eq!(sub[0x55], 0x0f);
+        let mut inv = sub;
+        inv_sub(&mut inv);
+        assert_eq!(inv[0x0f], 0x55);
+        assert!(Op::Mul.parse_key("0302").is_err());
+        let mut key = vec![Op::Mul.parse_key("0305").unwrap()];
+        Pipeline::parse("mul,xor").unwrap().pad_key(&mut key);
+        assert_eq!(key, vec![vec![3, 5], vec![0, 0]]);
+        let mut key = vec![vec![], vec![0x13]];
+        p.pad_key(&mut key);
+        assert_eq!(key[0], vec![1]);
+    }
+
+    #[test]
+    fn null_preserving_xor() {
+        let p = Pipeline::parse("nxor").unwrap();
+        let mut sub = [0usize; 256];
+        p.gen_sub(&[0x42], &mut sub);
+        assert_eq!(sub[0], 0);
+        assert_eq!(sub[0x42], 0x42);
+        assert_eq!(sub[0x41], 0x03);
+        let mut inv = sub;
+        inv_sub(&mut inv);
+        for i in 0usize..256 {
+            assert_eq!(inv[sub[i]], i);
+        }
+    }
+
+    #[test]
+    fn stepped_keys() {
+        let p = Pipeline::parse("xor,add").unwrap();
+        let key = vec![vec![0x10, 0x20, 0x30], vec![1, 2, 3]];
+        let subs = p.gen_stepped_subs(&key, Step::Inc, 0);
+        assert_eq!(subs.len(), 768);
+        let periodic = p.gen_key_subs(&key);
+        for (i, s) in subs.iter().enumerate() {
+            assert!(s[..] == periodic[i % 3][..]);
+        }
+        let subs = p.gen_stepped_subs(&key, Step::Inc, 3);
+        assert_eq!(subs[4][0], (0x20 + 12 + 2) as usize);
+        assert_eq!(subs[257][0], (0x30 + 3 + 3) as usize);
+        let subs = Pipeline::parse("xor").unwrap().gen_stepped_subs(&[vec![0x42]], Step::Idx, 1);
+        assert_eq!(subs.len(), 256);
+        assert_eq!(subs[7][0], 0x45);
+        assert_eq!(Step::from_name("idx"), Some(Step::Idx));
+    }
+
+    #[test]
+    fn key_space_enumeration() {
+        let p = Pipeline::parse("rol,xor").unwrap();
+        assert_eq!(p.key_space(), 8 * 256);
+        assert_eq!(p.index_params(0), vec![0, 0]);
+        assert_eq!(p.index_params(3 * 256 + 0x42), vec![3, 0x42]);
+        assert_eq!(p.name(), "rol-xor");
+    }
+
+    #[test]
+    fn keys() {
+        let p = Pipeline::parse("rol,mix").unwrap();
+        let mut key = vec![p.ops[0].parse_key("0301").unwrap(), p.ops[1].parse_key("[1,2,3,4,5,6,7,0]").unwrap()];
+        p.pad_key(&mut key);
+        assert_eq!(p.key_length(&key), 2);
+        assert_eq!(p.params_at(&key, 0), vec![3, 5913]);
+        assert_eq!(p.params_at(&key, 1), vec![1, 0]);
+        p.set_params_at(&mut key, 1, &[7, 1]);
+        assert_eq!(p.format_key(&key), "r = 0307 m = [1,2,3,4,5,6,7,0][0,1,2,3,4,5,7,6]");
+        assert!(p.ops[0].parse_key("08").is_err());
+        assert!(Pipeline::parse("xor,not").is_err());
+    }
+}
@@ -0,0 +1,238 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2015 Charles Hubain <github@haxelion.eu>
+*/
+
+//! Xor, addition and subtraction of whole 16, 32 or 64 bits words.
+//!
+//! The input is split in words starting at offset 0, trailing bytes not
+//! filling a word are left unchanged. Additions propagate carries from the
+//! least significant byte to the next one, so unlike the pipelines a key
+//! position is not a byte substitution. Keys are written as hex numbers, one
+//! word after the other, whatever the endianness of the input.
+
+use super::parse_hex;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WordOp {Xor, Add, Sub}
+
+impl WordOp {
+    /// Decipher the byte c of significance s given the key byte of the same
+    /// significance and the carry (or borrow) of the lower bytes. Returns the
+    /// plaintext byte and the carry of the next byte.
+    pub fn decrypt_byte(&self, c : u8, k : u8, carry : u8) -> (u8, u8) {
+        match *self {
+            WordOp::Xor => (c ^ k, 0),
+            WordOp::Add => {
+                let p = c.wrapping_sub(k).wrapping_sub(carry);
+                (p, ((p as u16 + k as u16 + carry as u16) >> 8) as u8)
+            },
+            WordOp::Sub => {
+                let p = c.wrapping_add(k).wrapping_add(carry);
+                (p, ((p as u16) < k as u16 + carry as u16) as u8)
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Endian {Little, Big}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Word {
+    pub op : WordOp,
+    /// Number of bytes of a word.
+    pub width : usize,
+    pub endian : Endian
+}
+
+impl Word {
+    /// Parse names like `xor32`, `add16be` or `sub64le`. Words are little
+    /// endian when not specified.
+    pub fn parse(s : &str) -> Option<Word> {
+        let (op, rest) = match s.get(..3) {
+            Some("xor") => (WordOp::Xor, &s[3..]),
+            Some("add") => (WordOp::Add, &s[3..]),
+            Some("sub") => (WordOp::Sub, &s[3..]),
+            _ => return None
+        };
+        let (bits, endian) = if let Some(b) = rest.strip_suffix("be") {
+            (b, Endian::Big)
+        }
+        else {
+            (rest.strip_suffix("le").unwrap_or(rest), Endian::Little)
+        };
+        let width = match bits {
+            "16" => 2,
+            "32" => 4,
+            "64" => 8,
+            _ => return None
+        };
+        Some(Word { op, width, endian })
+    }
+
+    pub fn name(&self) -> String {
+        let op = match self.op {
+            WordOp::Xor => "xor",
+            WordOp::Add => "add",
+            WordOp::Sub => "sub",
+        };
+        let endian = match self.endian {
+            Endian::Little => "le",
+            Endian::Big => "be",
+        };
+        format!("{}{}{}", op, self.width * 8, endian)
+    }
+
+    /// Letter naming the key, like the pipeline operations.
+    pub fn letter(&self) -> char {
+        match self.op {
+            WordOp::Xor => 'x',
+            WordOp::Add => 'a',
+            WordOp::Sub => 's',
+        }
+    }
+
+    /// Significance of the byte at position b of a word, 0 being the least
+    /// significant byte.
+    pub fn significance(&self, b : usize) -> usize {
+        match self.endian {
+            Endian::Little => b,
+            Endian::Big => self.width - 1 - b,
+        }
+    }
+
+    /// Parse a key made of hex words, it must have a whole number of words.
+    pub fn parse_key(&self, s : &str) -> Result<Vec<u8>, String> {
+        let k = parse_hex(s)?;
+        if k.is_empty() || k.len() % self.width != 0 {
+            return Err(format!("{} is not made of {} bits words", s, self.width * 8));
+        }
+        Ok(k)
+    }
+
+    /// Key byte of significance s of the j-th word of the key.
+    pub fn key_byte(&self, key : &[u8], j : usize, s : usize) -> u8 {
+        key[j * self.width + self.width - 1 - s]
+    }
+
+    fn words(&self, key : &[u8]) -> Vec<u64> {
+        key.chunks(self.width).map(|w| w.iter().fold(0u64, |v, b| (v << 8) | *b as u64)).collect()
+    }
+
+    fn read(&self, b : &[u8]) -> u64 {
+        (0..self.width).fold(0u64, |v, i| v | (b[i] as u64) << (8 * self.significance(i)))
+    }
+
+    fn write(&self, v : u64, b : &mut [u8]) {
+        for (i, x) in b.iter_mut().enumerate() {
+            *x = (v >> (8 * self.significance(i))) as u8;
+        }
+    }
+
+    fn apply(&self, data : &mut [u8], key : &[u8], encrypt : bool) {
+        let words = self.words(key);
+        let mask = if self.width == 8 { !0u64 } else { (1u64 << (8 * self.width)) - 1 };
+        for (i, chunk) in data.chunks_exact_mut(self.width).enumerate() {
+            let v = self.read(chunk);
+            let k = words[i % words.len()];
+            let e = match (self.op, encrypt) {
+                (WordOp::Xor, _) => v ^ k,
+                (WordOp::Add, true) | (WordOp::Sub, false) => v.wrapping_add(k),
+                (WordOp::Add, false) | (WordOp::Sub, true) => v.wrapping_sub(k),
+            };
+            self.write(e & mask, chunk);
+        }
+    }
+
+    pub fn encrypt(&self, data : &mut [u8], key : &[u8]) {
+        self.apply(data, key, true);
+    }
+
+    pub fn decrypt(&self, data : &mut [u8], key : &[u8]) {
+        self.apply(data, key, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for name in ["xor16le", "xor32be", "add32le", "add32be", "sub64le", "sub16be"].iter() {
+            assert_eq!(Word::parse(name).unwrap().name(), *name);
+        }
+        assert_eq!(Word::parse("add32").unwrap().name(), "add32le");
+        assert_eq!(Word::parse("xor32").unwrap().name(), "xor32le");
+        assert!(Word::parse("add24").is_none());
+        assert!(Word::parse("xor32bee").is_none());
+        assert!(Word::parse("xor").is_none());
+    }
+
+    #[test]
+    fn carries() {
+        let le = Word::parse("add32le").unwrap();
+        let be = Word::parse("add32be").unwrap();
+        let key = le.parse_key("000001ff").unwrap();
+        let mut data = vec![0x01, 0xff, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x42];
+        le.encrypt(&mut data, &key);
+        assert_eq!(data, vec![0x00, 0x01, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x42]);
+        let mut data = vec![0x00, 0x00, 0xff, 0x01, 0x42];
+        be.encrypt(&mut data, &key);
+        assert_eq!(data, vec![0x00, 0x01, 0x01, 0x00, 0x42]);
+        let sub = Word::parse("sub16be").unwrap();
+        let mut data = vec![0x01, 0x00];
+        sub.encrypt(&mut data, &sub.parse_key("0001").unwrap());
+        assert_eq!(data, vec![0x00, 0xff]);
+    }
+
+    #[test]
+    fn decrypt_inverts_encrypt() {
+        let plain : Vec<u8> = (0..1001).map(|i| (i * 37 + i / 7) as u8).collect();
+        for name in ["xor16", "xor32be", "add32le", "add32be", "sub32le", "sub64be", "add64le"].iter() {
+            let w = Word::parse(name).unwrap();
+            let key : Vec<u8> = (0..3 * w.width).map(|i| (i * 91 + 200) as u8).collect();
+            let mut data = plain.clone();
+            w.encrypt(&mut data, &key);
+            assert!(data != plain);
+            w.decrypt(&mut data, &key);
+            assert_eq!(data, plain);
+        }
+    }
+
+    #[test]
+    fn bytewise_decryption() {
+        let plain : Vec<u8> = (0..64).map(|i| (i * 151 + 3) as u8).collect();
+        for name in ["xor32", "add32le", "add32be", "sub32le", "sub16be"].iter() {
+            let w = Word::parse(name).unwrap();
+            let key : Vec<u8> = (0..2 * w.width).map(|i| (i * 77 + 129) as u8).collect();
+            let mut data = plain.clone();
+            w.encrypt(&mut data, &key);
+            for (i, word) in data.chunks(w.width).enumerate() {
+                let mut carry = 0u8;
+                for s in 0..w.width {
+                    let b = (0..w.width).find(|b| w.significance(*b) == s).unwrap();
+                    let k = w.key_byte(&key, i % 2, s);
+                    let (p, c) = w.op.decrypt_byte(word[b], k, carry);
+                    assert_eq!(p, plain[i * w.width + b]);
+                    carry = c;
+                }
+            }
+        }
+    }
+}
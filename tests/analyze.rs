@@ -0,0 +1,61 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, sample, xor_key, xor_model};
+use subbuster::{Metric, crack};
+use subbuster::analyze::{Class, entropy_map, regions, reliability_threshold, unlikely_substitution};
+use subbuster::lcg::Lcg;
+
+#[test]
+fn regions_of_a_container() {
+    let corpus = corpus();
+    let sample = sample();
+    let mut data = corpus[..8192].to_vec();
+    data.extend_from_slice(&xor_model().encipher(&xor_key(), &corpus[8192..16384])[..]);
+    data.extend_from_slice(&Lcg::from_name("msvc").unwrap().keystream(1, 8192)[..]);
+    data.extend(std::iter::repeat_n(0u8, 4096));
+    let windows = entropy_map(&data[..], 4096, 10, Some(&sample), &Metric::L2);
+    let found : Vec<(usize, usize, Class)> = regions(&windows[..]).iter().map(|r| (r.start, r.end, r.class)).collect();
+    assert!(found == vec![(0, 8192, Class::Plaintext), (8192, 16384, Class::Substitution(4)),
+                          (16384, 24576, Class::Random), (24576, 28672, Class::Constant)]);
+    let windows = entropy_map(&corpus[..8192], 4096, 10, None, &Metric::L2);
+    assert!(windows.iter().all(|w| w.class == Class::Plaintext));
+    // A maximum key length of 0 still considers length 1.
+    let windows = entropy_map(&corpus[..8192], 4096, 0, None, &Metric::L2);
+    assert!(windows.iter().all(|w| w.class == Class::Plaintext && w.period == 1));
+}
+
+#[test]
+fn random_bytes() {
+    let sample = sample();
+    let model = xor_model();
+    let random = Lcg::from_name("msvc").unwrap().keystream(42, 4096);
+    let data = model.encipher(&xor_key(), &corpus()[..4096]);
+    assert!(unlikely_substitution(&random[..], 10));
+    assert!(!unlikely_substitution(&data[..], 10));
+    let found = crack(std::slice::from_ref(&model), &[&data[..]], &sample, &Metric::L2, 10, 0).unwrap();
+    assert!(found.score > reliability_threshold(&*model, 4096, found.length, 0, &sample, &Metric::L2));
+    let found = crack(std::slice::from_ref(&model), &[&random[..]], &sample, &Metric::L2, 10, 0).unwrap();
+    assert!(found.score < reliability_threshold(&*model, 4096, found.length, 0, &sample, &Metric::L2));
+    // Large inputs are calibrated on a capped number of random bytes.
+    assert_eq!(reliability_threshold(&*model, 1 << 30, 4, 0, &sample, &Metric::L2),
+               reliability_threshold(&*model, 1 << 16, 4, 0, &sample, &Metric::L2));
+}
@@ -0,0 +1,59 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{TempDir, corpus, sample, xor_model};
+use std::fs;
+use subbuster::{Metric, output_paths};
+use subbuster::batch::{crack_files, expand_inputs, matches_wildcard};
+
+#[test]
+fn wildcards() {
+    assert!(matches_wildcard(b"*.bin", b"dump.bin"));
+    assert!(matches_wildcard(b"dump?.*", b"dump1.bin"));
+    assert!(!matches_wildcard(b"*.bin", b"dump.bin.txt"));
+    assert!(!matches_wildcard(b"dump?", b"dump"));
+}
+
+#[test]
+fn batch_outputs() {
+    let corpus = corpus();
+    let sample = sample();
+    let model = xor_model();
+    let temp = TempDir::new("batch");
+    let dir = temp.path();
+    let keys = [vec![0x13, 0x37], vec![0x42], vec![0xde, 0xad, 0xbe, 0xef]];
+    for (j, k) in keys.iter().enumerate() {
+        let data = model.encipher(std::slice::from_ref(k), &corpus[4096 * j..4096 * (j + 1)]);
+        fs::write(dir.join(format!("{}.bin", j)), &data).unwrap();
+    }
+    fs::write(dir.join("notes.txt"), b"not enciphered").unwrap();
+    let pattern = format!("{}/*.bin", dir.display());
+    let files = expand_inputs(&[pattern][..]).unwrap();
+    assert_eq!(files.len(), 3);
+    let outputs = output_paths(&files[..], &dir.join("out"));
+    fs::create_dir(dir.join("out")).unwrap();
+    let results = crack_files(&[model], &files[..], &sample, &Metric::L2, 10, 0, 2, Some(&outputs[..]));
+    for (j, r) in results.iter().enumerate() {
+        assert_eq!(r.candidate.as_ref().unwrap().key, vec![keys[j].clone()]);
+        assert!(r.error.is_none());
+        assert_eq!(fs::read(&outputs[j]).unwrap(), &corpus[4096 * j..4096 * (j + 1)]);
+    }
+}
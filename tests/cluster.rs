@@ -0,0 +1,42 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, sample, xor_model};
+use subbuster::{Metric, crack};
+use subbuster::cluster::cluster_keys;
+
+#[test]
+fn rotated_keys() {
+    let corpus = corpus();
+    let sample = sample();
+    let model = xor_model();
+    let keys = [vec![vec![0x13, 0x37, 0x42, 0x42]], vec![vec![0xde, 0xad]],
+                    vec![vec![0x37, 0x42, 0x42, 0x13]], vec![vec![0x13, 0x37, 0x42, 0x42, 0x13, 0x37, 0x42, 0x42]]];
+    let candidates : Vec<_> = keys.iter().enumerate().map(|(j, k)| {
+        let data = model.encipher(k, &corpus[4096 * j..4096 * (j + 1)]);
+        crack(std::slice::from_ref(&model), &[&data[..]], &sample, &Metric::L2, 10, 0)
+    }).collect();
+    let clusters = cluster_keys(&candidates[..]);
+    assert_eq!(clusters.len(), 2);
+    let phases : Vec<(usize, usize)> = clusters[0].members.iter().map(|m| (m.file, m.phase)).collect();
+    assert_eq!(phases, vec![(0, 0), (2, 1), (3, 0)]);
+    assert_eq!(clusters[1].key, keys[1]);
+}
@@ -0,0 +1,67 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+// Setup shared by the integration tests, each of them only using a part of it.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use subbuster::{CipherModel, Sample, parse_model};
+
+// Plaintext enciphered by the tests.
+pub fn corpus() -> Vec<u8> {
+    include_bytes!("../../LICENSE").to_vec()
+}
+
+// Sample made of the corpus.
+pub fn sample() -> Sample {
+    Sample::from_data(corpus())
+}
+
+pub fn xor_model() -> Box<dyn CipherModel> {
+    parse_model("xor").unwrap()
+}
+
+// Xor key of the tests which only need one.
+pub fn xor_key() -> Vec<Vec<u8>> {
+    vec![vec![0x13, 0x37, 0x42, 0xde]]
+}
+
+// Temporary directory removed when dropped, even if the test fails.
+pub struct TempDir {
+    path : PathBuf
+}
+
+impl TempDir {
+    pub fn new(name : &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("subbuster-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+        TempDir {path}
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
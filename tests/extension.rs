@@ -0,0 +1,139 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, sample, xor_model};
+use subbuster::rng::Rng;
+use subbuster::{CipherModel, Columns, Metric, Sample, Scorer};
+use subbuster::{crack, mean_similarity, same_subs, search_columns};
+
+// A model defined outside of the crate: the nibbles are swapped then xored.
+#[derive(Clone, Copy)]
+struct SwapXorModel;
+
+fn gen_swap_xor_sub(x : u8, sub : &mut [usize; 256]) {
+    for (i, s) in sub.iter_mut().enumerate() {
+        *s = (((i << 4) | (i >> 4)) & 0xff) ^ x as usize;
+    }
+}
+
+impl CipherModel for SwapXorModel {
+    fn name(&self) -> String {
+        "swap-xor".to_string()
+    }
+
+    fn key_bits(&self) -> f64 {
+        8f64
+    }
+
+    fn key_length(&self, key : &[Vec<u8>]) -> usize {
+        key[0].len()
+    }
+
+    fn gen_key_subs(&self, key : &[Vec<u8>]) -> Vec<[usize; 256]> {
+        key[0].iter().map(|x| {
+            let mut sub = [0usize; 256];
+            gen_swap_xor_sub(*x, &mut sub);
+            sub
+        }).collect()
+    }
+
+    fn format_key(&self, key : &[Vec<u8>]) -> String {
+        key[0].iter().map(|b| format!("{:02x}", *b)).collect()
+    }
+
+    fn truncate_key(&self, key : &[Vec<u8>], period : usize) -> Vec<Vec<u8>> {
+        vec![key[0][..period].to_vec()]
+    }
+
+    fn random_key(&self, rng : &mut Rng, l : usize) -> Vec<Vec<u8>> {
+        vec![(0..l).map(|_| rng.gen_u8()).collect()]
+    }
+
+    fn break_key(&self, columns : &Columns, sample : &Sample, scorer : &dyn Scorer,
+                 key : &mut Vec<Vec<u8>>) -> f64 {
+        let results = search_columns(columns, |_, u| {
+            let mut sub = [0usize; 256];
+            let mut best = (0u8, f64::INFINITY);
+            for x in 0usize..256 {
+                gen_swap_xor_sub(x as u8, &mut sub);
+                let s = scorer.distance(&sample.unigram, u, &sub);
+                if s < best.1 {
+                    best = (x as u8, s);
+                }
+            }
+            best
+        });
+        *key = vec![results.iter().map(|r| r.0).collect()];
+        let distances : Vec<f64> = results.iter().map(|r| r.1).collect();
+        mean_similarity(scorer, sample, &distances[..])
+    }
+
+    fn box_clone(&self) -> Box<dyn CipherModel> {
+        Box::new(*self)
+    }
+}
+
+// A scorer defined outside of the crate: the total variation distance.
+struct TotalVariation;
+
+impl Scorer for TotalVariation {
+    fn name(&self) -> String {
+        "total-variation".to_string()
+    }
+
+    fn distance(&self, u1 : &[f64; 256], u2 : &[f64; 256], s : &[usize; 256]) -> f64 {
+        (0..256).fold(0f64, |d, i| d + (u1[i] - u2[s[i]]).abs()) / 2f64
+    }
+
+    fn similarity(&self, d : f64) -> f64 {
+        1f64 - d
+    }
+}
+
+#[test]
+fn downstream_model() {
+    let corpus = corpus();
+    let sample = sample();
+    let key = vec![vec![0x13u8, 0x37u8, 0x42u8]];
+    let subs = SwapXorModel.gen_key_subs(&key);
+    let data : Vec<u8> = corpus.iter().enumerate().map(|(i, b)| subs[i % 3][*b as usize] as u8).collect();
+    let models = [Box::new(SwapXorModel) as Box<dyn CipherModel>];
+    let found = crack(&models[..], &[&data[..]], &sample, &Metric::L2, 10, 0).unwrap();
+    assert_eq!(found.model.name(), "swap-xor");
+    assert!(same_subs(&SwapXorModel, &key, &*found.model, &found.key));
+}
+
+#[test]
+fn downstream_scorer() {
+    let corpus = corpus();
+    let sample = sample();
+    let key = vec![vec![0x13u8, 0x37u8, 0x42u8]];
+    let xor = xor_model();
+    let data = xor.encipher(&key, &corpus[..8192]);
+    let found = crack(std::slice::from_ref(&xor), &[&data[..]], &sample, &TotalVariation, 10, 0).unwrap();
+    assert_eq!(found.key, key);
+    assert!(found.score > TotalVariation.score_plaintext(&sample, &data[..]));
+    let models = [Box::new(SwapXorModel) as Box<dyn CipherModel>];
+    let subs = SwapXorModel.gen_key_subs(&key);
+    let data : Vec<u8> = corpus.iter().enumerate().map(|(i, b)| subs[i % 3][*b as usize] as u8).collect();
+    let found = crack(&models[..], &[&data[..]], &sample, &TotalVariation, 10, 0).unwrap();
+    assert!(same_subs(&SwapXorModel, &key, &*found.model, &found.key));
+}
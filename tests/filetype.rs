@@ -0,0 +1,69 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, xor_key, xor_model};
+use subbuster::Candidate;
+use subbuster::filetype::{MAGIC_BONUS, boost_candidates, describe, has_magic};
+
+fn pe_header() -> Vec<u8> {
+    let mut pe : Vec<u8> = std::iter::repeat_n(0u8, 0x100).collect();
+    pe[0] = b'M';
+    pe[1] = b'Z';
+    pe[0x3c] = 0x80;
+    pe[0x80..0x86].clone_from_slice(b"PE\0\0\x64\x86");
+    pe
+}
+
+#[test]
+fn signatures() {
+    let mut dos = pe_header();
+    dos[0x80] = 0;
+    assert_eq!(&describe(&pe_header()[..])[..], "PE32+ executable (x86-64)");
+    assert_eq!(&describe(&dos[..])[..], "MS-DOS executable");
+    assert_eq!(&describe(b"\x7fELF\x02\x01\x01\0\0\0\0\0")[..], "ELF 64-bit");
+    assert_eq!(&describe(b"PK\x03\x04\x14\0\0\0\x08\0")[..], "Zip archive");
+    assert_eq!(&describe(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3")[..], "PDF document");
+    assert_eq!(&describe(b"\x1f\x8b\x08\0\0\0\0\0\0\x03")[..], "gzip compressed data");
+    assert_eq!(&describe(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR")[..], "PNG image");
+    assert_eq!(&describe(b"#!/bin/sh\necho hello\n")[..], "sh script");
+    assert_eq!(&describe(b"#!/usr/bin/env python\nprint(1)\n")[..], "python script");
+    assert_eq!(&describe(b"\n  <!DOCTYPE HTML>\n<html></html>")[..], "HTML document");
+    assert_eq!(&describe(&corpus()[..])[..], "text");
+    assert_eq!(&describe(b"\x13\x37\x42\xde\x00\xff\x80\x01")[..], "data");
+}
+
+#[test]
+fn header_bonus() {
+    let model = xor_model();
+    let mut plain = pe_header();
+    plain.extend_from_slice(&corpus()[..4096]);
+    let key = xor_key();
+    let cipher = model.encipher(&key, &plain[..]);
+    let mut candidates = [Candidate {score : 0.9, length : 4, model : model.clone(), key : key.clone()},
+        Candidate {score : 0.9, length : 4, model : model.clone(), key : vec![vec![0x12, 0x37, 0x42, 0xde]]},
+        Candidate {score : 0f64, length : 4, model : model.clone(), key : key.clone()}];
+    assert!(has_magic(&candidates[0], &cipher[..], 0));
+    assert!(!has_magic(&candidates[1], &cipher[..], 0));
+    boost_candidates(&mut candidates[..], &cipher[..], 0);
+    assert_eq!(candidates[0].score, 0.9 + MAGIC_BONUS);
+    assert_eq!(candidates[1].score, 0.9);
+    assert_eq!(candidates[2].score, 0f64);
+}
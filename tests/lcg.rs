@@ -0,0 +1,77 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, sample};
+use subbuster::Metric;
+use subbuster::lcg::{Lcg, decipher, find_byte_lcgs, find_seed_stat, find_seeds_known};
+
+#[test]
+fn known_plaintext_seed() {
+    let plain = corpus();
+    let msvc = Lcg::from_name("msvc").unwrap();
+    let data = decipher(&msvc, 0xc0ffee, &plain[..]);
+    let matches = find_seeds_known(&msvc, &data[..], 100, &plain[100..106], 0, 1 << 24, 10);
+    assert!(matches.iter().any(|m| m.seed == 0xc0ffee));
+    for m in matches.iter() {
+        assert_eq!(decipher(&m.lcg, m.seed, &data[..]), plain);
+    }
+}
+
+#[test]
+fn statistical_seed() {
+    let plain = corpus();
+    let sample = sample();
+    let minstd = Lcg::from_name("minstd").unwrap();
+    let data = decipher(&minstd, 31337, &plain[..]);
+    let best = find_seed_stat(&minstd, &data[..], &sample, &Metric::L2, 1, 1 << 16);
+    assert_eq!(best.seed, 31337);
+}
+
+#[test]
+fn custom_generator() {
+    let plain = corpus();
+    let custom = Lcg::from_name("1103515245,12345,2147483648,16").unwrap();
+    assert_eq!(custom.keystream(1, 2), Lcg::from_name("ansic").unwrap().keystream(1, 2));
+    let byte = Lcg::new("byte", 37, 101, 256, 0);
+    let data = decipher(&byte, 77, &plain[..]);
+    let matches = find_byte_lcgs(&data[..], 10, &plain[10..14], 0, 10);
+    assert!(!matches.is_empty());
+    assert!(matches.iter().any(|m| decipher(&m.lcg, m.seed, &data[..]) == plain));
+}
+
+#[test]
+fn shifted_custom_generator() {
+    let plain = corpus();
+    let custom = Lcg::from_name("1103515245,12345,4294967296,8").unwrap();
+    let data = decipher(&custom, 0xdeadbeef, &plain[..]);
+    let matches = find_byte_lcgs(&data[..], 10, &plain[10..26], 8, 10);
+    // Generators whose states are close to the right ones agree with the known
+    // plaintext before drifting away.
+    assert!(matches.iter().any(|m| decipher(&m.lcg, m.seed, &data[..]) == plain));
+    for m in matches.iter() {
+        assert_eq!(m.lcg.m, 1 << 16);
+        assert_eq!(&decipher(&m.lcg, m.seed, &data[..])[10..26], &plain[10..26]);
+    }
+    // Power of two moduli above 2^32 wrap instead of overflowing.
+    let wide = Lcg::from_name("6364136223846793005,1442695040888963407,9223372036854775808,56").unwrap();
+    let s = !0u64 >> 1;
+    assert_eq!(wide.step(s), s.wrapping_mul(wide.a).wrapping_add(wide.c) & s);
+}
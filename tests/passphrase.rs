@@ -0,0 +1,45 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, sample, xor_model};
+use subbuster::{Columns, Metric};
+use subbuster::passphrase::{complete_key, key_similarities, key_text, low_confidence, parse_wordlist};
+
+#[test]
+fn key_as_text() {
+    assert_eq!(&key_text(b"s3cr\"t\x01")[..], "\"s3cr\\\"t\\x01\"");
+    let words = parse_wordlist(b"hunter\r\npass\n\nbad\x01\n");
+    assert_eq!(words, vec![b"hunter".to_vec(), b"pass".to_vec()]);
+}
+
+#[test]
+fn dictionary_completion() {
+    let corpus = corpus();
+    let sample = sample();
+    let key = b"hunter2pass".to_vec();
+    // Too short for every key byte to be found.
+    let data = xor_model().encipher(std::slice::from_ref(&key), &corpus[5000..5120]);
+    let similarities = key_similarities(&Columns::new(&[&data[..]], 11, 0), &sample, &Metric::L2);
+    assert!(low_confidence(&similarities[..]).contains(&5));
+    let words = parse_wordlist(b"hunter\nhunted\nhunter2\npass\npassword\nletmein\nmonkey2\nsass\n");
+    let candidates = complete_key(&similarities[..], &words[..], 3);
+    assert_eq!(candidates[0].1, key);
+}
@@ -0,0 +1,55 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, sample, xor_key, xor_model};
+use subbuster::{Metric, Scorer, crack};
+use subbuster::rng::Rng;
+
+#[test]
+fn bounded_similarity() {
+    for m in ["l2", "chi2", "kl", "bhattacharyya"].iter() {
+        let metric = Metric::from_name(m).unwrap();
+        for d in [0f64, 0.5, 2f64, 100f64].iter() {
+            let s = metric.similarity(*d);
+            assert!((0f64..=1f64).contains(&s), "{} similarity of {} is {}", m, d, s);
+        }
+    }
+}
+
+#[test]
+fn comparable_metrics() {
+    let sample = sample();
+    let model = xor_model();
+    let key = xor_key();
+    let data = model.encipher(&key, &corpus()[..8192]);
+    let mut rng = Rng::new(42);
+    let random : Vec<u8> = (0..65536).map(|_| rng.gen_u8()).collect();
+    for m in ["l2", "chi2", "kl", "bhattacharyya"].iter() {
+        let metric = Metric::from_name(m).unwrap();
+        let found = crack(std::slice::from_ref(&model), &[&data[..]], &sample, &metric, 10, 0).unwrap();
+        assert_eq!(found.key, key);
+        // Random bytes score about 1/2 and the right key close to 1 whatever
+        // the metric.
+        let r = metric.score_plaintext(&sample, &random[..]);
+        assert!((r - 0.5).abs() < 0.05, "{} scores random bytes {}", m, r);
+        assert!(found.score > 0.9, "{} scores the key {}", m, found.score);
+    }
+}
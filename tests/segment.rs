@@ -0,0 +1,56 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, sample, xor_key, xor_model};
+use subbuster::{Metric, parse_model};
+use subbuster::segment::find_segments;
+
+#[test]
+fn key_changes() {
+    let corpus = corpus();
+    let sample = sample();
+    let model = xor_model();
+    let keys = [xor_key().remove(0), b"k3y".to_vec(), vec![0x99, 0x01, 0xaa, 0x55, 0x77, 0x10, 0x21]];
+    let cuts = [0usize, 5000, 11111, 20000];
+    let mut data : Vec<u8> = Vec::new();
+    for (i, k) in keys.iter().enumerate() {
+        data.extend_from_slice(&model.encipher(std::slice::from_ref(k), &corpus[cuts[i]..cuts[i+1]])[..]);
+    }
+    let segments = find_segments(&[model], &data[..], &sample, &Metric::L2, 10, 1024);
+    assert_eq!(segments.len(), 3);
+    for (i, s) in segments.iter().enumerate() {
+        // The boundaries are found up to a few bytes which decipher the same.
+        assert!(s.start + 8 >= cuts[i] && s.start <= cuts[i] + 8);
+        assert_eq!(s.candidate.as_ref().unwrap().key, vec![keys[i].clone()]);
+    }
+}
+
+#[test]
+fn subs_periods() {
+    // The boundaries are refined with the period of the substitution tables,
+    // which is longer than the key for the stream models.
+    for (name, key) in [("xor", vec![vec![1u8, 2, 3]]), ("xor,add", vec![vec![1u8, 2], vec![3u8, 4, 5]]),
+                        ("xor-inc", vec![vec![1u8, 2, 3], vec![1u8]])] {
+        let model = parse_model(name).unwrap();
+        assert_eq!(model.subs_period(&key), model.gen_key_subs(&key).len());
+    }
+    assert_eq!(parse_model("xor-inc").unwrap().subs_period(&[vec![1u8, 2, 3], vec![1u8]]), 768);
+}
@@ -0,0 +1,174 @@
+/*
+This file is part of SubBuster.
+
+SubBuster is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+SubBuster is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+Copyright 2014 Charles Hubain <github@haxelion.eu>
+*/
+
+mod common;
+
+use common::{corpus, sample, xor_model};
+use subbuster::{CipherModel, Columns, Metric, crack, decipher, find_block_size, normalize_key, parse_model, same_subs};
+use subbuster::model::constrained_model;
+use subbuster::bench::{BenchConfig, recovery_rate, run_bench};
+
+fn bench_model(model : Box<dyn CipherModel>, lengths : Vec<usize>, sizes : Vec<usize>, trials : usize) -> f64 {
+    let corpus = corpus();
+    let sample = sample();
+    let mut config = BenchConfig::new();
+    config.models = vec![model.clone()];
+    config.lengths = lengths;
+    config.sizes = sizes;
+    config.trials = trials;
+    let results = run_bench(&corpus[..], &sample, &config);
+    assert_eq!(results.len(), config.lengths.len() * config.sizes.len());
+    recovery_rate(&results[..], &*model)
+}
+
+#[test]
+fn xor_recovery() {
+    assert!(bench_model(xor_model(), vec![1, 2, 3, 4, 8], vec![4096, 16384], 4) >= 0.95);
+}
+
+#[test]
+fn xor_add_recovery() {
+    assert!(bench_model(parse_model("xor-add").unwrap(), vec![1, 2, 4], vec![4096, 16384], 2) >= 0.9);
+}
+
+#[test]
+fn null_preserving_xor_recovery() {
+    assert!(bench_model(parse_model("nxor").unwrap(), vec![1, 2, 4], vec![4096], 2) >= 0.9);
+}
+
+#[test]
+fn affine_recovery() {
+    assert!(bench_model(parse_model("affine").unwrap(), vec![1, 2], vec![16384], 2) >= 0.75);
+}
+
+#[test]
+fn block_recovery() {
+    let corpus = corpus();
+    let sample = sample();
+    let model = xor_model();
+    let key = vec![vec![0x13, 0x37, 0x42]];
+    let plain = &corpus[..8192];
+    let mut data : Vec<u8> = Vec::new();
+    for chunk in plain.chunks(512) {
+        data.extend_from_slice(&model.encipher(&key, chunk)[..]);
+    }
+    assert_eq!(find_block_size(&[&data[..]], 10), 512);
+    let found = crack(&[model], &[&data[..]], &sample, &Metric::L2, 10, 512).unwrap();
+    assert_eq!(found.key, key);
+    assert_eq!(&decipher(&*found.model, &found.key, &data[..], 512)[..], plain);
+}
+
+#[test]
+fn many_time_pad_recovery() {
+    let corpus = corpus();
+    let sample = sample();
+    let model = xor_model();
+    let key = vec![vec![0x13, 0x37, 0x42, 0xde, 0xad, 0xbe, 0xef, 0x99]];
+    // Most of these inputs are too short to be broken alone.
+    let data : Vec<Vec<u8>> = (0..20).map(|j| model.encipher(&key, &corpus[1500 * j..1500 * j + 64])).collect();
+    let inputs : Vec<&[u8]> = data.iter().map(|d| &d[..]).collect();
+    let found = crack(&[model], &inputs[..], &sample, &Metric::L2, 10, 0).unwrap();
+    assert_eq!(found.key, key);
+    for (j, d) in inputs.iter().enumerate() {
+        assert_eq!(&decipher(&*found.model, &found.key, d, 0)[..], &corpus[1500 * j..1500 * j + 64]);
+    }
+}
+
+#[test]
+fn independent_periods_recovery() {
+    let corpus = corpus();
+    let sample = sample();
+    let model = parse_model("xor-add").unwrap();
+    let key = vec![vec![0x80, 0x01, 0x82, 0x03], vec![0x10, 0x91, 0x12]];
+    assert_eq!(model.key_length(&key), 12);
+    let data = model.encipher(&key, &corpus[..16384]);
+    let found = crack(std::slice::from_ref(&model), &[&data[..]], &sample, &Metric::L2, 12, 0).unwrap();
+    assert!(same_subs(&*model, &key, &*found.model, &found.key));
+    assert_eq!(found.key[0].len(), 4);
+    assert_eq!(found.key[1].len(), 3);
+}
+
+#[test]
+fn equivalent_keys() {
+    let level3 = parse_model("3").unwrap();
+    let key = vec![vec![0x93, 0x05], vec![0x10, 0xa2], vec![0x12, 0x34, 0x00, 0x01]];
+    let flipped = vec![vec![0x13, 0x85], vec![0x90, 0x22], vec![0x12, 0x34, 0x00, 0x01]];
+    assert!(same_subs(&*level3, &key, &*level3, &flipped));
+    let (m1, k1) = normalize_key(&*level3, &key);
+    let (m2, k2) = normalize_key(&*level3, &flipped);
+    assert_eq!(m1.name(), m2.name());
+    assert_eq!(k1, k2);
+    assert_eq!(k1[0], vec![0x13, 0x05]);
+}
+
+#[test]
+fn constrained_recovery() {
+    let corpus = corpus();
+    let sample = sample();
+    let fixed = ["a=1337".to_string(), "m=[1,2,3,4,5,6,7,0][7,6,5,4,3,2,1,0]".to_string()];
+    let ranges = ["x=printable".to_string()];
+    let model = constrained_model("3", &fixed[..], &ranges[..]).unwrap();
+    let level3 = parse_model("3").unwrap();
+    let key = vec![vec![0x6b, 0x21], vec![0x13, 0x37], vec![0x17, 0x19, 0x9d, 0x7f]];
+    let data = level3.encipher(&key, &corpus[..8192]);
+    let found = crack(&[model], &[&data[..]], &sample, &Metric::L2, 10, 0).unwrap();
+    assert!(same_subs(&*level3, &key, &*found.model, &found.key));
+    assert!(constrained_model("3", &["a=1".to_string()][..], &[][..]).is_err());
+    assert!(constrained_model("xor", &[][..], &["x=7f-20".to_string()][..]).is_err());
+    assert!(constrained_model("xor", &["é=00".to_string()][..], &[][..]).is_err());
+    assert!(constrained_model("xor", &["xé=00".to_string()][..], &[][..]).is_err());
+    // Key lengths which aren't a multiple of the period of the constraints are
+    // extended.
+    let xor = xor_model();
+    let fixed = constrained_model("xor", &["x=41??42".to_string()][..], &[][..]).unwrap();
+    assert_eq!(fixed.key_period(), 3);
+    let data = xor.encipher(&[vec![0x41, 0x13, 0x42]], &corpus[..8192]);
+    let mut key : Vec<Vec<u8>> = Vec::new();
+    assert!(fixed.break_key(&Columns::new(&[&data[..]], 2, 0), &sample, &Metric::L2, &mut key) > 0.9);
+    assert_eq!(key[0], vec![0x41, 0x13, 0x42, 0x41, 0x13, 0x42]);
+}
+
+// Pipelines failing their check aren't searched.
+#[test]
+fn unsearchable_pipeline() {
+    let corpus = corpus();
+    let sample = sample();
+    let unsearchable = parse_model("mix,mix").unwrap();
+    assert!(unsearchable.check().is_err());
+    assert!(crack(&[unsearchable], &[&corpus[..8192]], &sample, &Metric::L2, 10, 0).is_none());
+}
+
+#[test]
+fn word_add_recovery() {
+    assert!(bench_model(parse_model("add32le").unwrap(), vec![4, 8], vec![4096], 2) >= 0.75);
+    assert!(bench_model(parse_model("sub16be").unwrap(), vec![2, 4], vec![4096], 2) >= 0.75);
+    assert!(bench_model(parse_model("xor32be").unwrap(), vec![4], vec![4096], 2) >= 0.75);
+}
+
+#[test]
+#[ignore]
+fn xor_add_mix_recovery() {
+    assert!(bench_model(parse_model("xor-add-mix").unwrap(), vec![1, 2], vec![16384], 2) >= 0.75);
+}
+
+#[test]
+#[ignore]
+fn stream_recovery() {
+    assert!(bench_model(parse_model("xor-inc").unwrap(), vec![1], vec![4096], 2) >= 0.5);
+}